@@ -0,0 +1,18 @@
+use std::process::{Command, Stdio};
+
+fn main() {
+    // The tests running ffmpeg are only ignored when it is not installed
+    println!("cargo::rustc-check-cfg=cfg(has_ffmpeg)");
+    println!("cargo::rerun-if-changed=build.rs");
+    println!("cargo::rerun-if-env-changed=PATH");
+
+    let has_ffmpeg = Command::new("ffmpeg")
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if has_ffmpeg {
+        println!("cargo::rustc-cfg=has_ffmpeg");
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    ffi::OsStr,
     fs::Permissions,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
@@ -6,26 +7,31 @@ use std::{
 
 use crossbeam_channel::{Receiver, Sender};
 use miette::{miette, Context, IntoDiagnostic, Result};
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    database::{CacheDb, Sqlite},
-    io::{find_unused_prefix, named_tempfile, touch},
-    outside::StreamTransformer,
-    types::{Bitrate, Extension, Timestamp},
-    utils::MutexUtils,
+    cli::{AppArgs, CoverArt, LoudnormScope, Normalize, Tracklist},
+    database::{CacheDb, ClipIdx, Sqlite},
+    io::{
+        find_unused_prefix, hash_file, named_tempfile, named_tempfile_in, touch,
+        OUTPUT_TEMPFILE_PREFIX, STALE_TEMPFILE_AGE,
+    },
+    outside::{AudioFilter, StreamTransformer},
+    types::{
+        Codec, Encoding, Extension, LoudnessMeasure, Tag, Tags, TemplateValues, TimeOffset,
+        Timestamp, Timestamps,
+    },
+    utils::{report_oneline, MutexUtils},
 };
 
-use super::{Actor, TimestampedClip, VideoTitle};
+use super::{Actor, StreamInfo, TimestampedClip, VideoTitle};
 
 #[derive(Debug)]
 pub struct ClipperActor<'a> {
     id: usize,
     stream_tsf: &'a dyn StreamTransformer,
-    out_dir: &'a Path,
-    ext: Extension,
+    args: &'a AppArgs,
     cache: &'a Sqlite,
-    bitrate: Bitrate,
 
     receive_channel: Option<Receiver<TimestampedClip>>,
     send_channel: Option<Sender<VideoTitle>>,
@@ -52,72 +58,38 @@ impl Actor<TimestampedClip, VideoTitle> for ClipperActor<'_> {
             .ok_or_else(|| miette!("Send channel not set"))?;
 
         if self.id == 0 {
-            self.delete_empty_files()
-                .wrap_err("Could not delete empty files")?;
+            Self::delete_empty_files(&self.args.out).wrap_err("Could not delete empty files")?;
         }
 
         debug!("Actor started, waiting for a downloaded stream");
 
-        for TimestampedClip {
-            stream_info,
-            start,
-            end,
-            clip_idx,
-        } in receive_channel
-        {
-            let video_id = &stream_info.video_id;
-            let stream_file = &stream_info.stream_file;
-            let metadata = &stream_info.metadata;
-
-            debug!("Stream '{}' received", video_id);
-            if end.is_none() && metadata.title == start.title {
-                info!("Clipping '{}' entire stream into one file", metadata.title);
-            } else {
-                info!(
-                    "Clipping '{}' ({} - {}) into '{}'",
-                    metadata.title,
-                    start.t_start,
-                    end.as_ref().map_or("END", |end| end.t_start.as_str()),
-                    start.title
-                );
-            }
-
-            let out_empty = Self::reserve_output_path(self.out_dir, &start.title, self.ext);
-            let out_tmp = named_tempfile(self.ext).wrap_err("Could not create tempfile")?;
-
-            // Create clip to tempfile (slow, things may go bad)
-            let album = format!("{} ({})", metadata.title, video_id);
-            self.create_clip(
-                stream_file.path(),
-                out_tmp.path(),
-                &start,
-                end.as_ref(),
-                &album,
-            )
-            .wrap_err("Could not create clip")?;
-
-            let output = out_empty.with_extension(self.ext.with_no_dot());
-
-            // When finished, move to output file (fast, nearly no errors)
-            // First try to do a simple move
-            if std::fs::rename(&out_tmp, &output).is_err() {
-                debug!("Moving file failed, falling back to copying");
-                std::fs::copy(&out_tmp, &output).unwrap();
+        for clip in receive_channel {
+            let stream_info = clip.stream_info.clone();
+            let title = clip.timestamp.title.clone();
+            let clip_idx = clip.clip_idx;
+
+            debug!("Stream '{}' received", stream_info.video_id);
+            if let Err(report) = self.process_clip(clip) {
+                let report = report.wrap_err(format!(
+                    "Could not process clip '{title}' of video {}",
+                    stream_info.video_id
+                ));
+                error!("{report:?}");
+                self.cache
+                    .fail_work(stream_info.db_id, clip_idx, &report_oneline(&report))?;
             }
 
-            // Change the permissions if possible
-            self.set_output_file_permission(&output);
-
-            self.cache.complete_work(stream_info.db_id, clip_idx)?;
-
-            // Remove the placeholder
-            std::fs::remove_file(out_empty).unwrap();
-
-            info!("Clip '{}' completed", start.title);
-
             // If last clip processed, add video_id to cache
             if Arc::strong_count(&stream_info) == 1 {
-                self.cache.set_video_as_completed(stream_info.db_id)?;
+                let remaining = self.cache.count_remaining_work(stream_info.db_id)?;
+                if remaining == 0 {
+                    self.cache.set_video_as_completed(stream_info.db_id)?;
+                } else {
+                    warn!(
+                        "'{}' has {remaining} failed clips, they will be retried on the next run",
+                        stream_info.metadata.title
+                    );
+                }
             }
 
             debug!("Iteration completed. Waiting for next clip");
@@ -132,24 +104,108 @@ impl<'a> ClipperActor<'a> {
     pub fn new(
         id: usize,
         stream_tsf: &'a dyn StreamTransformer,
-        out_dir: &'a Path,
-        ext: Extension,
+        args: &'a AppArgs,
         cache: &'a Sqlite,
-        bitrate: Bitrate,
     ) -> Self {
         Self {
             id,
             stream_tsf,
-            out_dir,
-            ext,
+            args,
             cache,
-            bitrate,
             receive_channel: None,
             send_channel: None,
         }
     }
 
-    /// Create an empty placeholder for the clip in the output directory.
+    /// Create the clip output file and mark the clip work as completed
+    fn process_clip(&self, clip: TimestampedClip) -> Result<()> {
+        let TimestampedClip {
+            stream_info,
+            timestamp,
+            clip_idx,
+        } = clip;
+
+        let video_id = &stream_info.video_id;
+        let metadata = &stream_info.metadata;
+
+        if timestamp.t_end.is_none() && metadata.title == timestamp.title {
+            info!("Clipping '{}' entire stream into one file", metadata.title);
+        } else {
+            info!(
+                "Clipping '{}' ({} - {}) into '{}'",
+                metadata.title,
+                timestamp.t_start,
+                timestamp
+                    .t_end
+                    .map_or_else(|| "END".to_string(), |t_end| t_end.to_string()),
+                timestamp.title
+            );
+        }
+
+        let out_relative = self.args.out_template.render(
+            &TemplateValues {
+                uploader: &metadata.uploader,
+                video_title: &metadata.title,
+                video_id,
+                track: track_number(&stream_info, clip_idx).unwrap_or(0),
+                clip_title: &timestamp.title,
+                artist: timestamp.artist.as_deref().unwrap_or(&metadata.uploader),
+                ext: self.args.ext,
+            },
+            self.args.ascii_filenames,
+        );
+        let out_empty = Self::reserve_output_path(&self.args.out, &out_relative, self.args.ext)
+            .wrap_err("Could not reserve the output path")?;
+
+        let res = (|| {
+            // Create the tempfile next to the output, so that it can simply be renamed
+            let out_dir = out_empty.parent().unwrap_or(&self.args.out);
+            let out_tmp =
+                named_tempfile_in(out_dir, self.args.ext).wrap_err("Could not create tempfile")?;
+
+            // Create clip to tempfile (slow, things may go bad)
+            let tags = self.clip_tags(&stream_info, &timestamp, clip_idx);
+            let settings = self
+                .create_clip(&stream_info, out_tmp.path(), &timestamp, tags)
+                .wrap_err("Could not create clip")?;
+
+            // When finished, move to output file (fast, nearly no errors)
+            let output = out_empty.with_extension(self.args.ext.with_no_dot());
+            out_tmp
+                .persist(&output)
+                .into_diagnostic()
+                .wrap_err("Could not move clip to the output directory")?;
+
+            // Change the permissions if possible
+            self.set_output_file_permission(&output);
+
+            if let Some(tracklist) = self
+                .tracklist(&stream_info, &timestamp)
+                .filter(|_| self.args.tracklist.contains(&Tracklist::Cue))
+            {
+                self.write_cue(&stream_info, tracklist, &output);
+            }
+
+            self.cache
+                .complete_work(stream_info.db_id, clip_idx, &output, &settings)
+        })();
+
+        // Remove the placeholder, whether the clip has been created or not
+        if let Err(err) = std::fs::remove_file(&out_empty) {
+            warn!(
+                "Could not remove placeholder '{}': {err}",
+                out_empty.display()
+            );
+        }
+
+        res?;
+        info!("Clip '{}' completed", timestamp.title);
+        Ok(())
+    }
+
+    /// Create an empty placeholder for the clip in the output directory,
+    /// at the given path relative to it (without extension).
+    /// The parent directories are created if needed.
     ///
     /// This will return a path to the placeholder with a ".empty" extension
     /// such that when replacing the extension with the given one, it can be
@@ -159,65 +215,341 @@ impl<'a> ClipperActor<'a> {
     /// method calls.
     /// This however assumes that the output directory is not changing outside
     /// of this method during the call.
-    fn reserve_output_path(out_dir: &Path, title: &str, extension: Extension) -> PathBuf {
+    fn reserve_output_path(
+        out_dir: &Path,
+        relative: &Path,
+        extension: Extension,
+    ) -> Result<PathBuf> {
         static LOCK: Mutex<()> = Mutex::new(());
 
+        let path = out_dir.join(relative);
+        let dir = path.parent().unwrap_or(out_dir);
+        let name = path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| miette!("Invalid output file name '{}'", path.display()))?;
+
         LOCK.with_lock(|_lock| {
-            let mut output = find_unused_prefix(out_dir, title, extension, true)
-                .context("Could not build output file path")
-                .unwrap();
+            std::fs::create_dir_all(dir)
+                .into_diagnostic()
+                .wrap_err("Could not create output directories")?;
+
+            let mut output = find_unused_prefix(dir, name, extension, true)
+                .context("Could not build output file path")?;
 
             // Use the .empty extension for the placeholder
             output.set_extension("empty");
 
-            touch(&output).unwrap();
+            touch(&output)?;
 
-            output
+            Ok(output)
         })
     }
 
+    /// Construct the enabled tags of the clip
+    fn clip_tags(
+        &self,
+        stream_info: &StreamInfo,
+        timestamp: &Timestamp,
+        clip_idx: ClipIdx,
+    ) -> Tags {
+        let metadata = &stream_info.metadata;
+        let enabled = |tag| self.args.tag.contains(&tag);
+
+        Tags {
+            title: enabled(Tag::Title).then(|| timestamp.title.clone()),
+            artist: enabled(Tag::Artist).then(|| {
+                timestamp
+                    .artist
+                    .clone()
+                    .unwrap_or_else(|| metadata.uploader.clone())
+            }),
+            album: enabled(Tag::Album)
+                .then(|| format!("{} ({})", metadata.title, stream_info.video_id)),
+            track: track_number(stream_info, clip_idx)
+                .filter(|_| enabled(Tag::Track))
+                .map(|track| (track, stream_info.nb_clips)),
+            date: metadata
+                .upload_date
+                .as_deref()
+                .filter(|_| enabled(Tag::Date))
+                .map(format_date),
+            comment: metadata.url.clone().filter(|_| enabled(Tag::Comment)),
+            gain: vec![],
+        }
+    }
+
     /// Create a clip of a stream.
     ///
-    /// `input` stream will be cut to keep only data from timestamps `start` to `end`
-    /// and will be saved to `output`. The `album` metadata will be added to the file.
+    /// The stream will be cut to keep only data between the `timestamp` bounds
+    /// and will be saved to `output`. The `tags` metadata will be added to the file,
+    /// along with the stream thumbnail as cover art and the stream tracklist as chapters
+    /// if configured.
+    ///
+    /// If the end is not specified, clip will continue until the end of the stream.
     ///
-    /// If `end` is not specified, clip will continue until the end of the stream.
+    /// Return a description of the audio settings used to create the clip,
+    /// e.g. `loudnorm I=-24:LRA=7:TP=-2, opus 96K`.
     fn create_clip(
         &self,
-        input: &Path,
+        stream_info: &StreamInfo,
         output: &Path,
-        start: &Timestamp,
-        end: Option<&Timestamp>,
-        album: &str,
-    ) -> Result<()> {
-        // Create a temporary file with the correct extension
+        timestamp: &Timestamp,
+        mut tags: Tags,
+    ) -> Result<String> {
+        let input = stream_info.stream_file.path();
+        let (codec, bitrate) = (self.args.codec, self.args.bitrate);
+
+        let (normalization, mode) = match self.args.normalize {
+            Normalize::Loudnorm if self.args.loudnorm_scope == LoudnormScope::Album => {
+                let gain = self
+                    .args
+                    .loudness
+                    .linear_gain(&self.album_loudness(stream_info)?);
+                (
+                    Some(AudioFilter::Gain(gain)),
+                    format!("loudnorm album {}, gain {gain:.2} dB", self.args.loudness),
+                )
+            }
+            Normalize::Loudnorm => {
+                let measured = self.measure_loudness(stream_info, Some(timestamp))?;
+                (
+                    Some(AudioFilter::Loudnorm {
+                        target: self.args.loudness,
+                        measured,
+                    }),
+                    format!("loudnorm {}", self.args.loudness),
+                )
+            }
+            Normalize::ReplayGain => {
+                let track = self.measure_loudness(stream_info, Some(timestamp))?;
+                // The clip gain is enough for the players, do not fail without the album one
+                let album = self
+                    .album_loudness(stream_info)
+                    .map_err(|report| warn!("Writing only the track gain: {report:?}"))
+                    .ok();
+                tags.set_gain(&track, album.as_ref(), codec == Codec::Opus);
+                (None, "replaygain".to_owned())
+            }
+            Normalize::None => (None, "none".to_owned()),
+        };
+
+        let mut settings = vec![mode];
+        let mut filters = vec![];
+
+        // The fade-out is placed from the clip duration, before the silence is trimmed
+        if self.args.fade_out > 0.0 {
+            let t_end = timestamp
+                .t_end
+                .unwrap_or_else(|| TimeOffset::from_secs(stream_info.metadata.duration));
+            let duration = t_end.saturating_sub(timestamp.t_start).as_secs_f64();
+            filters.push(AudioFilter::FadeOut {
+                start: (duration - self.args.fade_out).max(0.0),
+                duration: self.args.fade_out,
+            });
+            settings.push(format!("fade out {}s", self.args.fade_out));
+        }
+        // Then trim the silence, so that the fade-in is applied on the actual audio
+        if self.args.trim_silence {
+            filters.push(AudioFilter::TrimSilence {
+                threshold: self.args.silence_threshold,
+                min_duration: self.args.silence_min_duration,
+                end_gap: self.args.split_min_gap,
+            });
+            settings.push(format!("trim silence {}dB", self.args.silence_threshold));
+        }
+        if self.args.fade_in > 0.0 {
+            filters.push(AudioFilter::FadeIn(self.args.fade_in));
+            settings.push(format!("fade in {}s", self.args.fade_in));
+        }
+        filters.extend(normalization);
+
+        // Without any filter, avoid re-encoding the entire stream if possible.
+        // A trimmed clip is always encoded, as copying would align its bounds on the packets
+        let encoding = if filters.is_empty()
+            && timestamp.is_entire_stream()
+            && self
+                .stream_tsf
+                .audio_codec(input)
+                .wrap_err("Could not detect the audio codec")?
+                == codec.name()
+        {
+            Encoding::Copy
+        } else {
+            Encoding::Codec(codec, bitrate)
+        };
+        settings.push(encoding.to_string());
+        let settings = settings.join(", ");
+
+        let chapters = self
+            .tracklist(stream_info, timestamp)
+            .filter(|_| self.args.tracklist.contains(&Tracklist::Chapters));
+        let cover = stream_info
+            .thumbnail
+            .as_ref()
+            .filter(|_| self.args.cover_art != CoverArt::None);
+        if chapters.is_none() && cover.is_none() {
+            self.stream_tsf
+                .create_clip(input, output, timestamp, &filters, encoding, &tags)?;
+            return Ok(settings);
+        }
+
         let out_ext =
             Extension::from_path(output).ok_or_else(|| miette!("Invalid output extension"))?;
-        let tmp = named_tempfile(out_ext)?;
-
+        let mut clip = named_tempfile(out_ext)?;
         self.stream_tsf
-            .extract_clip(input, tmp.path(), start, end, album)
-            .wrap_err("Could not extract a clip of the audio file from the timestamps")?;
+            .create_clip(input, clip.path(), timestamp, &filters, encoding, &tags)?;
+
+        // The chapters are not essential, keep the clip without them on failure
+        if let Some(chapters) = chapters {
+            let with_chapters = named_tempfile(out_ext)?;
+            let duration = TimeOffset::from_secs(stream_info.metadata.duration);
+            match self.stream_tsf.embed_chapters(
+                clip.path(),
+                with_chapters.path(),
+                chapters,
+                duration,
+                &tags,
+            ) {
+                Ok(()) => clip = with_chapters,
+                Err(report) => warn!("Could not embed the chapters, skipping them: {report:?}"),
+            }
+        }
 
-        self.stream_tsf
-            .normalize_audio(tmp.path(), output, self.bitrate)
-            .wrap_err("Could not normalize audio")?;
+        // The cover art is not essential, keep the clip without it on failure
+        let crop_square = self.args.cover_art == CoverArt::Square;
+        let with_cover = cover.is_some_and(|cover| {
+            self.stream_tsf
+                .embed_cover_art(clip.path(), output, cover.path(), crop_square, &tags)
+                .map_err(|report| warn!("Could not embed the cover art, skipping it: {report:?}"))
+                .is_ok()
+        });
+        if !with_cover {
+            std::fs::copy(clip.path(), output)
+                .into_diagnostic()
+                .wrap_err("Could not copy the clip to the output")?;
+        }
 
-        Ok(())
+        Ok(settings)
+    }
+
+    /// Return the tracklist to write for the clip, if it covers the entire stream
+    fn tracklist<'s>(
+        &self,
+        stream_info: &'s StreamInfo,
+        timestamp: &Timestamp,
+    ) -> Option<&'s Timestamps> {
+        Some(&stream_info.tracklist)
+            .filter(|tracklist| timestamp.is_entire_stream() && !tracklist.is_empty())
+    }
+
+    /// Write the tracklist of the entire stream as a CUE sheet next to its output file.
+    ///
+    /// The CUE sheet is not essential, so failures are only reported.
+    fn write_cue(&self, stream_info: &StreamInfo, tracklist: &Timestamps, output: &Path) {
+        let metadata = &stream_info.metadata;
+        let file_name = output.file_name().unwrap_or_default().to_string_lossy();
+        let cue = tracklist.to_cue(&metadata.uploader, &metadata.title, &file_name);
+
+        let cue_path = output.with_extension("cue");
+        if let Err(e) = std::fs::write(&cue_path, cue) {
+            warn!(
+                "Could not write the CUE sheet '{}': {e}",
+                cue_path.display()
+            );
+        }
+    }
+
+    /// Return the loudness of the entire stream, measuring it if no other clip already has
+    fn album_loudness(&self, stream_info: &StreamInfo) -> Result<LoudnessMeasure> {
+        stream_info
+            .album_loudness
+            .get_or_init(|| {
+                self.measure_loudness(stream_info, None)
+                    .map_err(|report| report_oneline(&report))
+            })
+            .clone()
+            .map_err(|error| miette!("Could not measure the loudness of the stream: {error}"))
+    }
+
+    /// Measure the loudness of the stream between the timestamp bounds,
+    /// or of the entire stream if `None`.
+    ///
+    /// The measures are cached, and reused as long as the stream file has not changed.
+    fn measure_loudness(
+        &self,
+        stream_info: &StreamInfo,
+        timestamp: Option<&Timestamp>,
+    ) -> Result<LoudnessMeasure> {
+        let db_id = stream_info.db_id;
+        let input = stream_info.stream_file.path();
+
+        // The cache is only an optimization, measure anyway if it cannot be used
+        let hash = stream_info
+            .stream_hash
+            .get_or_init(|| hash_file(input).map_err(|report| report_oneline(&report)))
+            .as_ref()
+            .map_err(|error| warn!("Could not hash the stream file, not using the cache: {error}"))
+            .ok();
+
+        if let Some(hash) = hash {
+            match self.cache.loudness(db_id, timestamp, hash) {
+                Ok(Some(measure)) => {
+                    debug!("Reusing the cached loudness measure");
+                    return Ok(measure);
+                }
+                Ok(None) => (),
+                Err(report) => warn!("{report:?}"),
+            }
+        }
+
+        if timestamp.is_none() {
+            info!("Measuring the loudness of '{}'", stream_info.metadata.title);
+        }
+        let measure = self
+            .stream_tsf
+            .measure_loudness(input, timestamp)
+            .wrap_err("Could not measure the loudness")?;
+
+        if let Some(hash) = hash {
+            if let Err(report) = self.cache.set_loudness(db_id, timestamp, hash, &measure) {
+                warn!("{report:?}");
+            }
+        }
+
+        Ok(measure)
     }
 
-    /// Delete every file with the "empty" extension in the output directory
-    fn delete_empty_files(&self) -> Result<()> {
-        for entry in self
-            .out_dir
+    /// Delete every file with the "empty" extension in the directory and its subdirectories,
+    /// along with the temporary files left by an interrupted run.
+    ///
+    /// The recent temporary files are kept, as the other actors may be writing their clips.
+    fn delete_empty_files(dir: &Path) -> Result<()> {
+        for entry in dir
             .read_dir()
             .into_diagnostic()
             .wrap_err("Could not read output directory")?
         {
             let entry = entry.into_diagnostic()?;
             let path = entry.path();
-            if let Some(ext) = path.extension() {
-                if path.is_file() && ext.eq_ignore_ascii_case("empty") {
+            if entry.file_type().into_diagnostic()?.is_dir() {
+                Self::delete_empty_files(&path)?;
+            } else if path.is_file() {
+                let is_empty = path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("empty"));
+                let is_stale_tempfile = entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(OUTPUT_TEMPFILE_PREFIX)
+                    && entry
+                        .metadata()
+                        .and_then(|meta| meta.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .is_some_and(|age| age > STALE_TEMPFILE_AGE);
+
+                if is_empty || is_stale_tempfile {
                     if let Err(err) = std::fs::remove_file(&path) {
                         warn!("Could not remove file '{}': {}", path.display(), err);
                     }
@@ -245,3 +577,20 @@ impl<'a> ClipperActor<'a> {
         // For other systems, permissions settings are too limited
     }
 }
+
+/// Return the 1-based track number of the clip in the stream,
+/// or `None` for the entire stream kept along with the clips
+fn track_number(stream_info: &StreamInfo, clip_idx: ClipIdx) -> Option<usize> {
+    let idx = clip_idx as usize;
+    (idx < stream_info.nb_clips).then_some(idx + 1)
+}
+
+/// Format a `YYYYMMDD` date into `YYYY-MM-DD`.
+/// If the date is not in the expected format, return it as-is.
+fn format_date(date: &str) -> String {
+    if date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+    } else {
+        date.to_owned()
+    }
+}
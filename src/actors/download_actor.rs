@@ -1,25 +1,40 @@
-use std::path::Path;
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+};
 
 use crossbeam_channel::{Receiver, Sender};
 use miette::{miette, Context, IntoDiagnostic, Result};
-use regex::Regex;
+use tempfile::NamedTempFile;
 use tracing::{debug, error, info, trace, warn};
 
 use crate::{
-    database::{CacheDb, ProcessedState, Sqlite},
-    io::named_tempfile,
-    outside::StreamDownloader,
-    types::{Extension, Metadata, Timestamp, Timestamps},
+    cli::{AppArgs, CoverArt, Split, TimestampSource},
+    database::{self, CacheDb, ProcessedState, Sqlite},
+    io::{hash_file, named_tempfile, named_tempfile_with_suffix},
+    outside::{StreamDownloader, StreamTransformer},
+    stream_store::{StreamFile, StreamStore},
+    types::{Comment, Extension, Metadata, TimeOffset, Timestamp, Timestamps},
+    utils::report_oneline,
 };
 
 use super::{Actor, DownloadedStream, VideoId};
 
+/// A file overriding the timestamps of a video
+#[derive(Debug)]
+struct TimestampsOverride {
+    path: PathBuf,
+    /// The hash of the file content, to detect its changes
+    hash: String,
+}
+
 #[derive(Debug)]
 pub struct DownloadActor<'a> {
     stream_dl: &'a dyn StreamDownloader,
-    skip_timestamps: bool,
-    clip_regex: &'a [Regex],
+    stream_tsf: &'a dyn StreamTransformer,
+    args: &'a AppArgs,
     cache: &'a Sqlite,
+    stream_store: Option<&'a StreamStore>,
 
     receive_channel: Option<Receiver<VideoId>>,
     send_channel: Option<Sender<DownloadedStream>>,
@@ -50,21 +65,69 @@ impl Actor<VideoId, DownloadedStream> for DownloadActor<'_> {
         for video_id in receive_channel {
             debug!("Video ID '{video_id}' received");
 
-            let (db_id, video_state) = self.cache.check_video(&video_id)?;
+            let (db_id, mut video_state) = self.cache.check_video(&video_id)?;
+
+            let timestamps_override = match self.find_override(&video_id) {
+                Ok(timestamps_override) => timestamps_override,
+                Err(report) => {
+                    error!("Video {video_id} failed, skipping it: {report:?}");
+                    self.cache
+                        .set_video_as_failed(db_id, &report_oneline(&report))?;
+                    continue;
+                }
+            };
+            // Only a new or modified override file processes the video again,
+            // not a removed one nor one unused with the current settings
+            let override_hash = timestamps_override.as_ref().map(|o| o.hash.as_str());
+            if override_hash.is_some()
+                && self.cache.override_hash(db_id)?.as_deref() != override_hash
+            {
+                if video_state != ProcessedState::NotProcessed {
+                    info!("Timestamps override of video {video_id} changed, processing it again");
+                }
+                // The clips are the user's files, leave them to be deleted by hand
+                for clip in self.cache.reset_video(db_id, override_hash)? {
+                    warn!("The previous clip {clip:?} of video {video_id} is kept");
+                }
+                video_state = ProcessedState::NotProcessed;
+            }
+
             if video_state == ProcessedState::Completed {
                 debug!("Video already processed. Skipping it");
                 continue;
             }
 
+            let stored_stream = match self.stream_store {
+                Some(store) => store.get(self.cache, &video_id),
+                None => None,
+            };
+
             // Put mkv as the stream file format as:
             // - Not giving any will cause an error (even though it may write another file format)
             // - It should accept any kind of audio format
             // With that, the stream data should be copied as-is, without modification
-            let stream_file = named_tempfile(Extension::Mkv)?;
+            let stream_file = match (&stored_stream, self.stream_store) {
+                (Some(stored_stream), _) => StreamFile::Stored(stored_stream.path.clone()),
+                (None, Some(store)) => match store.tempfile() {
+                    Ok(file) => StreamFile::Temp(file),
+                    Err(report) => {
+                        warn!(
+                            "Could not create a file in the stream store, \
+                            downloading the stream outside of it: {report:?}"
+                        );
+                        StreamFile::Temp(named_tempfile(Extension::Mkv)?)
+                    }
+                },
+                (None, None) => StreamFile::Temp(named_tempfile(Extension::Mkv)?),
+            };
 
-            let (metadata, timestamps) = match self
-                .download_and_extract_metadata(&video_id, stream_file.path())
-            {
+            let (metadata, timestamps, tracklist) = match self.download_and_extract_metadata(
+                &video_id,
+                db_id,
+                stream_file.path(),
+                stored_stream.is_none(),
+                timestamps_override.as_ref(),
+            ) {
                 Ok(res) => res,
                 Err(crate::result::Error::UnavailableStream) => {
                     error!(
@@ -75,22 +138,54 @@ impl Actor<VideoId, DownloadedStream> for DownloadActor<'_> {
                     continue;
                 }
                 Err(crate::result::Error::Miette(report)) => {
-                    Err(report.wrap_err("Could not download and extract metadata and timestamps"))?
+                    let report =
+                        report.wrap_err("Could not download and extract metadata and timestamps");
+                    error!("Video {video_id} failed, skipping it: {report:?}");
+                    self.cache
+                        .set_video_as_failed(db_id, &report_oneline(&report))?;
+                    continue;
                 }
             };
 
+            let (stream_file, stream_hash) = match (stream_file, self.stream_store) {
+                (StreamFile::Temp(file), Some(store)) => store.store(self.cache, &video_id, file),
+                (stream_file, _) => (stream_file, stored_stream.map(|stream| stream.hash)),
+            };
+
+            let thumbnail = if self.args.cover_art != CoverArt::None {
+                self.download_thumbnail(&video_id)
+            } else {
+                None
+            };
+
             debug!("title       = {}", metadata.title);
             debug!("uploader    = {}", metadata.uploader);
             debug!("duration    = {}", metadata.duration);
+            debug!("chapters    = {}", metadata.chapters.len());
             debug!("description = {} bytes long", metadata.description.len());
             trace!("description = {}", metadata.description);
 
+            // Keep the entire video along with the clips, unless the only clip already is
+            let entire_stream = self.args.split == Split::Both
+                && !matches!(
+                    timestamps.as_slice(),
+                    [Timestamp {
+                        t_start: TimeOffset::ZERO,
+                        t_end: None,
+                        ..
+                    }]
+                );
+
             send_channel
                 .send(DownloadedStream {
                     video_id,
                     file: stream_file,
+                    stream_hash,
+                    thumbnail,
                     metadata,
                     timestamps,
+                    tracklist,
+                    entire_stream,
                     db_id,
                     video_state,
                 })
@@ -108,83 +203,301 @@ impl Actor<VideoId, DownloadedStream> for DownloadActor<'_> {
 impl<'a> DownloadActor<'a> {
     pub fn new(
         stream_dl: &'a dyn StreamDownloader,
-        skip_timestamps: bool,
-        clip_regex: &'a [Regex],
+        stream_tsf: &'a dyn StreamTransformer,
+        args: &'a AppArgs,
         cache: &'a Sqlite,
+        stream_store: Option<&'a StreamStore>,
     ) -> Self {
         Self {
             stream_dl,
-            skip_timestamps,
-            clip_regex,
+            stream_tsf,
+            args,
             cache,
+            stream_store,
             receive_channel: None,
             send_channel: None,
         }
     }
 
+    /// Download the video stream, unless it is already in `out`,
+    /// and extract its metadata and timestamps.
+    ///
+    /// Also return the tracklist of the stream, which is only extracted when
+    /// the entire video is kept and a tracklist must be written for it.
     fn download_and_extract_metadata(
         &self,
         video_id: &str,
+        db_id: database::VideoId,
         out: &Path,
-    ) -> crate::result::Result<(Metadata, Timestamps)> {
-        let metadata = self
+        download: bool,
+        timestamps_override: Option<&TimestampsOverride>,
+    ) -> crate::result::Result<(Metadata, Timestamps, Timestamps)> {
+        let mut metadata = self
             .stream_dl
             .get_metadata(video_id)
             .map_err(|err| err.wrap_err_with(|| "Could not get stream metadata"))?;
+        metadata.title = self.args.title_cleanup.clean_video_title(&metadata.title);
 
-        loop {
+        if download {
             info!("Downloading video {video_id}");
             self.stream_dl.download_audio(out, video_id)?;
+        } else {
+            info!("Reusing the stored stream of video {video_id}");
+        }
 
-            let mut timestamps = if self.skip_timestamps {
-                info!("Downloaded file, skip timestamps extraction");
-
-                Timestamps::new(vec![])
-            } else {
-                info!("Downloaded file, extracting timestamps");
+        let mut timestamps = if self.args.split == Split::Full && self.args.tracklist.is_empty() {
+            info!("Downloaded file, skip timestamps extraction");
 
-                let timestamps =
-                    Timestamps::extract_timestamps(&metadata.description, self.clip_regex);
+            Timestamps::new(vec![])
+        } else {
+            info!("Downloaded file, extracting timestamps");
 
-                debug!("Timestamps: {}", timestamps);
-                if !Self::is_file_complete(metadata.duration, &timestamps) {
-                    warn!("Downloaded file seems incomplete. Retry downloading it again");
-                    continue;
+            let timestamps = match timestamps_override {
+                Some(timestamps_override) => {
+                    self.read_override(&timestamps_override.path, &metadata)?
                 }
+                None => self.extract_timestamps(video_id, db_id, &metadata)?,
+            };
+            debug!("Timestamps: {}", timestamps);
+            timestamps
+        };
+
+        if timestamps.is_empty() && self.args.split == Split::Silence {
+            info!("No timestamp, splitting the video on silences");
+            timestamps = self.split_on_silences(out, &metadata)?;
+            debug!("Timestamps: {}", timestamps);
+        }
 
-                timestamps
+        let tracklist = match self.args.split {
+            // The timestamps of the entire video are only used as its tracklist
+            Split::Full => std::mem::replace(&mut timestamps, Timestamps::new(vec![])),
+            Split::Both if !self.args.tracklist.is_empty() => timestamps.clone(),
+            _ => Timestamps::new(vec![]),
+        };
+
+        if timestamps.is_empty() {
+            debug!("No timestamp. Clipping the entire video");
+            let start = Timestamp {
+                t_start: TimeOffset::ZERO,
+                t_end: None,
+                title: metadata.title.to_string(),
+                artist: None,
             };
 
-            if timestamps.is_empty() {
-                debug!("No timestamp. Clipping the entire video");
-                let start = Timestamp {
-                    t_start: "00:00".to_string(),
-                    title: metadata.title.to_string(),
-                };
+            timestamps = Timestamps::new(vec![start]);
+        }
+
+        Ok((metadata, timestamps, tracklist))
+    }
 
-                timestamps = Timestamps::new(vec![start]);
+    /// Download the video thumbnail.
+    ///
+    /// The thumbnail is not essential, so failures are only reported.
+    fn download_thumbnail(&self, video_id: &str) -> Option<NamedTempFile> {
+        let res = named_tempfile_with_suffix(".jpg").and_then(|thumbnail| {
+            self.stream_dl
+                .download_thumbnail(thumbnail.path(), video_id)
+                .map_err(miette::Report::from)?;
+            Ok(thumbnail)
+        });
+
+        match res {
+            Ok(thumbnail) => Some(thumbnail),
+            Err(report) => {
+                warn!("Could not download the thumbnail of video {video_id}: {report:?}");
+                None
             }
+        }
+    }
+
+    /// Extract the timestamps from the first source that gives any
+    ///
+    /// Timestamps rejected during the extraction are reported but do not prevent
+    /// the other ones from being used.
+    fn extract_timestamps(
+        &self,
+        video_id: &str,
+        db_id: database::VideoId,
+        metadata: &Metadata,
+    ) -> Result<Timestamps> {
+        let duration = TimeOffset::from_secs(metadata.duration);
+
+        for source in &self.args.timestamp_source {
+            let timestamps = match source {
+                TimestampSource::Chapters => Timestamps::from_chapters(
+                    &metadata.chapters,
+                    duration,
+                    self.args.parse_artist,
+                    &self.args.title_cleanup,
+                ),
+                TimestampSource::Description => {
+                    let (timestamps, rejected) = Timestamps::extract_timestamps(
+                        &metadata.description,
+                        "description",
+                        &self.args.clip_regex,
+                        duration,
+                        self.args.detect_tracklist,
+                        self.args.parse_artist,
+                        &self.args.title_cleanup,
+                    );
+                    for rejected in rejected {
+                        warn!("{:?}", miette::Report::new(rejected));
+                    }
+                    timestamps
+                }
+                TimestampSource::Comments => {
+                    self.extract_from_comments(video_id, db_id, duration)?
+                }
+            };
 
-            return Ok((metadata, timestamps));
+            if !timestamps.is_empty() {
+                debug!("Timestamps extracted from {source:?}");
+                return Ok(timestamps);
+            }
         }
+
+        Ok(Timestamps::new(vec![]))
     }
 
-    /// Verify that the file stream duration is longer than the latest timestamp.
+    /// Extract the timestamps from the comment pinned by the uploader, or else from
+    /// the most-liked comment with at least `comment_min_timestamps` timestamps.
     ///
-    /// If there is a timestamp after the stream end, it would mean that the file
-    /// download stopped before completing.
+    /// The comment used is recorded in the cache. The comments are not essential,
+    /// so failing to fetch them is only reported.
+    fn extract_from_comments(
+        &self,
+        video_id: &str,
+        db_id: database::VideoId,
+        duration: TimeOffset,
+    ) -> Result<Timestamps> {
+        let mut comments = match self
+            .stream_dl
+            .get_comments(video_id, self.args.max_comments)
+        {
+            Ok(comments) => comments,
+            Err(err) => {
+                let report = miette::Report::from(err);
+                warn!("Could not get the comments of video {video_id}: {report:?}");
+                return Ok(Timestamps::new(vec![]));
+            }
+        };
+        debug!("{} comments fetched", comments.len());
+
+        // The comment pinned by the uploader first, then the most-liked ones
+        comments.sort_by_key(|comment| {
+            (
+                Reverse(comment.is_pinned_by_uploader()),
+                Reverse(comment.like_count.unwrap_or(0)),
+            )
+        });
+
+        let extract = |comment: &Comment| {
+            // Rejections are expected in the comments which are not tracklists
+            let (timestamps, _) = Timestamps::extract_timestamps(
+                &comment.text,
+                &format!("comment {}", comment.id),
+                &self.args.clip_regex,
+                duration,
+                self.args.detect_tracklist,
+                self.args.parse_artist,
+                &self.args.title_cleanup,
+            );
+            timestamps
+        };
+        let found = comments.iter().find_map(|comment| {
+            let timestamps = extract(comment);
+            let min_timestamps = if comment.is_pinned_by_uploader() {
+                1
+            } else {
+                self.args.comment_min_timestamps.max(1)
+            };
+            (timestamps.len() >= min_timestamps).then_some((comment, timestamps))
+        });
+
+        match found {
+            Some((comment, timestamps)) => {
+                info!(
+                    "Timestamps extracted from the comment {} of {}",
+                    comment.id,
+                    comment.author.as_deref().unwrap_or("an unknown author")
+                );
+                self.cache.set_timestamps_comment(db_id, comment)?;
+                Ok(timestamps)
+            }
+            None => Ok(Timestamps::new(vec![])),
+        }
+    }
+
+    /// Create the timestamps of the tracks separated by silences in the downloaded stream
+    fn split_on_silences(&self, stream: &Path, metadata: &Metadata) -> Result<Timestamps> {
+        let silences = self
+            .stream_tsf
+            .detect_silences(stream, self.args.silence_threshold, self.args.split_min_gap)
+            .wrap_err("Could not detect the silences of the stream")?;
+
+        Ok(Timestamps::from_silences(
+            &silences,
+            TimeOffset::from_secs(metadata.duration),
+            TimeOffset::from_secs_f64(self.args.split_min_length)
+                .into_diagnostic()
+                .wrap_err("Invalid split_min_length")?,
+            &metadata.title,
+        ))
+    }
+
+    /// Find the file overriding the timestamps of the video in the overrides directory.
     ///
-    /// If there is no timestamp, return true.
-    fn is_file_complete(stream_duration: u64, timestamps: &Timestamps) -> bool {
-        // The minimum number of second the last clip must last for the stream to be considered complete
-        const MIN_CLIP_LENGTH: u64 = 10;
+    /// A CUE sheet is used before a text file. The timestamps are not overridden
+    /// if the entire videos are kept without tracklist.
+    fn find_override(&self, video_id: &str) -> Result<Option<TimestampsOverride>> {
+        let Some(dir) = &self.args.overrides_dir else {
+            return Ok(None);
+        };
+        if self.args.split == Split::Full && self.args.tracklist.is_empty() {
+            return Ok(None);
+        }
 
-        if let Some(last_timestamp) = timestamps.last() {
-            let last_secs = Timestamp::to_seconds(&last_timestamp.t_start);
+        for ext in ["cue", "txt"] {
+            let path = dir.join(format!("{video_id}.{ext}"));
+            if path.is_file() {
+                debug!("Timestamps override found at {path:?}");
+                let hash = hash_file(&path)
+                    .wrap_err_with(|| format!("Could not hash the override file {path:?}"))?;
+                return Ok(Some(TimestampsOverride { path, hash }));
+            }
+        }
+
+        Ok(None)
+    }
 
-            last_secs + MIN_CLIP_LENGTH < stream_duration
+    /// Read the timestamps of the override file, a CUE sheet or lines
+    /// in the same formats as the description
+    fn read_override(&self, path: &Path, metadata: &Metadata) -> Result<Timestamps> {
+        let content = std::fs::read_to_string(path)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not read the override file {path:?}"))?;
+        let duration = TimeOffset::from_secs(metadata.duration);
+
+        let timestamps = if path.extension().is_some_and(|ext| ext == "cue") {
+            Timestamps::from_cue(&content, duration, &self.args.title_cleanup)
         } else {
-            true
-        }
+            let (timestamps, rejected) = Timestamps::extract_timestamps(
+                &content,
+                &path.display().to_string(),
+                &self.args.clip_regex,
+                duration,
+                // The override file only contains the tracklist
+                false,
+                self.args.parse_artist,
+                &self.args.title_cleanup,
+            );
+            for rejected in rejected {
+                warn!("{:?}", miette::Report::new(rejected));
+            }
+            timestamps
+        };
+
+        debug!("Timestamps read from the override file {path:?}");
+        Ok(timestamps)
     }
 }
@@ -1,10 +1,11 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use tempfile::NamedTempFile;
 
 use crate::{
     database,
-    types::{Metadata, Timestamp, Timestamps},
+    stream_store::StreamFile,
+    types::{LoudnessMeasure, Metadata, Timestamp, Timestamps},
 };
 
 pub type VideoId = String;
@@ -13,23 +14,41 @@ pub type VideoTitle = String;
 #[derive(Debug)]
 pub struct DownloadedStream {
     pub video_id: String,
-    pub file: NamedTempFile,
+    pub file: StreamFile,
+    /// The hash of the stream file, if already known
+    pub stream_hash: Option<String>,
+    pub thumbnail: Option<NamedTempFile>,
     pub metadata: Metadata,
     pub timestamps: Timestamps,
+    /// The timestamps to write as the tracklist of the entire stream output
+    pub tracklist: Timestamps,
+    /// Whether to create a clip of the entire stream in addition to the timestamped ones
+    pub entire_stream: bool,
     pub db_id: database::VideoId,
     pub video_state: database::ProcessedState,
 }
 
 pub struct StreamInfo {
     pub video_id: String,
-    pub stream_file: NamedTempFile,
+    pub stream_file: StreamFile,
+    pub thumbnail: Option<NamedTempFile>,
     pub metadata: Metadata,
     pub db_id: database::VideoId,
+    /// The total number of timestamped clips of the stream.
+    /// A clip with this index is the entire stream kept in addition to them
+    pub nb_clips: usize,
+    /// The timestamps to write as the tracklist of the entire stream output.
+    /// Empty if no tracklist must be written
+    pub tracklist: Timestamps,
+    /// The loudness of the entire stream, measured by the first clip needing it
+    pub album_loudness: OnceLock<Result<LoudnessMeasure, String>>,
+    /// The hash of the stream file, computed by the first clip needing it
+    pub stream_hash: OnceLock<Result<String, String>>,
 }
 
 pub struct TimestampedClip {
     pub stream_info: Arc<StreamInfo>,
-    pub start: Timestamp,
-    pub end: Option<Timestamp>,
+    /// The clip timestamp, whose end has been resolved if it can be
+    pub timestamp: Timestamp,
     pub clip_idx: database::ClipIdx,
 }
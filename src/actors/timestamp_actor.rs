@@ -1,12 +1,18 @@
-use std::sync::Arc;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, OnceLock},
+};
 
 use crossbeam_channel::{Receiver, Sender};
 use miette::{miette, Context, IntoDiagnostic, Result};
-use tracing::{debug, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     actors::StreamInfo,
     database::{self, CacheDb, ProcessedState, Sqlite},
+    outside::StreamTransformer,
+    types::{TimeOffset, Timestamp},
+    utils::report_oneline,
 };
 
 use super::{Actor, DownloadedStream, TimestampedClip};
@@ -16,7 +22,13 @@ use super::{Actor, DownloadedStream, TimestampedClip};
 ///
 /// This enables the previous actor to directly download the next video
 /// instead of waiting that the next actor has received the last clip.
+///
+/// If a snap window is set, the clip bounds are moved to the quietest point
+/// around them before being dispatched.
 pub struct TimestampActor<'a> {
+    stream_tsf: &'a dyn StreamTransformer,
+    /// The maximum distance, in seconds, to move the clip bounds. Disabled if 0
+    snap_window: f64,
     cache: &'a Sqlite,
 
     receive_channel: Option<Receiver<DownloadedStream>>,
@@ -48,23 +60,28 @@ impl Actor<DownloadedStream, TimestampedClip> for TimestampActor<'_> {
         for DownloadedStream {
             video_id,
             file,
+            stream_hash,
+            thumbnail,
             metadata,
             timestamps,
+            tracklist,
+            entire_stream,
             db_id,
             video_state,
         } in receive_channel
         {
+            // The entire stream clip comes after the timestamped ones
+            let nb_work = timestamps.len() + usize::from(entire_stream);
+
             let work_indexes: Vec<database::ClipIdx> = match video_state {
-                ProcessedState::NotProcessed => {
+                ProcessedState::NotProcessed | ProcessedState::Failed => {
                     self.cache
-                        .assign_work(db_id, timestamps.len().try_into().unwrap())
+                        .assign_work(db_id, nb_work.try_into().unwrap())
                         .wrap_err("Could not assign work")?;
-                    (0..timestamps.len())
-                        .map(|n| n.try_into().unwrap())
-                        .collect()
+                    (0..nb_work).map(|n| n.try_into().unwrap()).collect()
                 }
                 ProcessedState::RemainingClips(v) => v,
-                ProcessedState::ProcessedClips(v) => (0..timestamps.len())
+                ProcessedState::ProcessedClips(v) => (0..nb_work)
                     .map(|n| n.try_into().unwrap())
                     .filter(|n| !v.contains(n))
                     .collect(),
@@ -80,32 +97,78 @@ impl Actor<DownloadedStream, TimestampedClip> for TimestampActor<'_> {
                 continue;
             }
 
-            if work_indexes.len() != timestamps.len() {
+            if work_indexes.len() != nb_work {
                 // Pending work
                 info!(
                     "Resuming work on '{}': {}/{} remaining",
                     &metadata.title,
                     work_indexes.len(),
-                    timestamps.len()
+                    nb_work
                 );
             }
 
             let stream_info = Arc::new(StreamInfo {
                 video_id,
                 stream_file: file,
+                thumbnail,
                 metadata,
                 db_id,
+                nb_clips: timestamps.len(),
+                tracklist,
+                album_loudness: OnceLock::new(),
+                // The hash of a stored stream is already known
+                stream_hash: match stream_hash {
+                    Some(hash) => OnceLock::from(Ok(hash)),
+                    None => OnceLock::new(),
+                },
             });
 
+            // No explicit end: the clip lasts until the start of the next one
+            let mut timestamps = timestamps.to_vec();
+            for i in 0..timestamps.len() {
+                if timestamps[i].t_end.is_none() {
+                    timestamps[i].t_end = timestamps.get(i + 1).map(|next| next.t_start);
+                }
+            }
+
+            let snapped = if self.snap_window > 0.0 {
+                Some(self.snap_bounds(&stream_info, &timestamps, &work_indexes))
+            } else {
+                None
+            };
+
             // Send every timestamped clip
             for clip_idx in work_indexes {
-                let start = timestamps[clip_idx as usize].clone();
-                let end = timestamps.get(clip_idx as usize + 1).cloned();
+                let timestamp = match timestamps.get(clip_idx as usize) {
+                    Some(timestamp) => {
+                        self.clip_bounds(&stream_info, clip_idx, timestamp, snapped.as_deref())
+                    }
+                    // The entire stream, after the timestamped clips
+                    None if entire_stream && clip_idx as usize == timestamps.len() => Timestamp {
+                        t_start: TimeOffset::ZERO,
+                        t_end: None,
+                        title: stream_info.metadata.title.clone(),
+                        artist: None,
+                    },
+                    // The timestamps changed since the work was assigned
+                    None => {
+                        let report =
+                            miette!("The video only has {} timestamps now", timestamps.len())
+                                .wrap_err(format!(
+                                    "Could not process clip {} of video {}",
+                                    clip_idx + 1,
+                                    stream_info.video_id
+                                ));
+                        error!("{report:?}");
+                        self.cache
+                            .fail_work(db_id, clip_idx, &report_oneline(&report))?;
+                        continue;
+                    }
+                };
                 send_channel
                     .send(TimestampedClip {
                         stream_info: stream_info.clone(),
-                        start,
-                        end,
+                        timestamp,
                         clip_idx,
                     })
                     .into_diagnostic()
@@ -121,11 +184,111 @@ impl Actor<DownloadedStream, TimestampedClip> for TimestampActor<'_> {
 }
 
 impl<'a> TimestampActor<'a> {
-    pub fn new(cache: &'a Sqlite) -> Self {
+    pub fn new(stream_tsf: &'a dyn StreamTransformer, snap_window: f64, cache: &'a Sqlite) -> Self {
         Self {
+            stream_tsf,
+            snap_window,
             cache,
             receive_channel: None,
             send_channel: None,
         }
     }
+
+    /// Return the bounds of the clip, moved to the `snapped` ones if any.
+    ///
+    /// The moved bounds are recorded in the cache.
+    fn clip_bounds(
+        &self,
+        stream_info: &StreamInfo,
+        clip_idx: database::ClipIdx,
+        timestamp: &Timestamp,
+        snapped: Option<&[Timestamp]>,
+    ) -> Timestamp {
+        let Some(snapped) = snapped else {
+            return timestamp.clone();
+        };
+
+        let adjusted = snapped[clip_idx as usize].clone();
+        if let Err(e) =
+            self.cache
+                .set_clip_bounds(stream_info.db_id, clip_idx, timestamp, &adjusted)
+        {
+            warn!("Could not record the bounds of clip {clip_idx}: {e:?}");
+        }
+        adjusted
+    }
+
+    /// Move the bounds of the clips to the quietest point in the snap window around them.
+    ///
+    /// Only the bounds of the clips to process are moved, and a bound shared by
+    /// consecutive clips is moved once for both. A bound never moves past the middle
+    /// of it and its neighbouring bounds, so that the clips stay in order.
+    /// The start and the end of the stream are not moved.
+    fn snap_bounds(
+        &self,
+        stream_info: &StreamInfo,
+        timestamps: &[Timestamp],
+        work_indexes: &[database::ClipIdx],
+    ) -> Vec<Timestamp> {
+        let duration = TimeOffset::from_secs(stream_info.metadata.duration);
+
+        let mut bounds: Vec<TimeOffset> = timestamps
+            .iter()
+            .flat_map(|timestamp| [Some(timestamp.t_start), timestamp.t_end])
+            .flatten()
+            .collect();
+        bounds.sort();
+        bounds.dedup();
+
+        let mut snapped = BTreeMap::new();
+        let mut snap = |bound: TimeOffset| -> TimeOffset {
+            if bound == TimeOffset::ZERO || bound >= duration {
+                return bound;
+            }
+
+            *snapped.entry(bound).or_insert_with(|| {
+                let idx = bounds.binary_search(&bound).unwrap();
+                let prev = idx.checked_sub(1).map_or(0.0, |i| bounds[i].as_secs_f64());
+                let next = bounds.get(idx + 1).unwrap_or(&duration).as_secs_f64();
+
+                let t = bound.as_secs_f64();
+                let from = (t - self.snap_window).max((prev + t) / 2.0);
+                let to = (t + self.snap_window).min((t + next) / 2.0);
+
+                match self.stream_tsf.quietest_point(
+                    stream_info.stream_file.path(),
+                    TimeOffset::from_secs_f64(from).unwrap_or(bound),
+                    TimeOffset::from_secs_f64(to).unwrap_or(bound),
+                ) {
+                    Ok(quietest) => {
+                        debug!("Moved clip bound {bound} to {quietest}");
+                        quietest
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Could not find the quietest point around {bound}, keeping it: {e:?}"
+                        );
+                        bound
+                    }
+                }
+            })
+        };
+
+        timestamps
+            .iter()
+            .enumerate()
+            .map(|(i, timestamp)| {
+                if work_indexes.contains(&i.try_into().unwrap()) {
+                    Timestamp {
+                        t_start: snap(timestamp.t_start),
+                        t_end: timestamp.t_end.map(&mut snap),
+                        title: timestamp.title.clone(),
+                        artist: timestamp.artist.clone(),
+                    }
+                } else {
+                    timestamp.clone()
+                }
+            })
+            .collect()
+    }
 }
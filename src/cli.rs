@@ -2,17 +2,22 @@ use std::path::PathBuf;
 
 use clap::{
     builder::{PossibleValue, PossibleValuesParser},
-    command, value_parser, Arg, ArgAction, ArgMatches, Command, ValueEnum, ValueHint,
+    command,
+    parser::ValueSource,
+    value_parser, Arg, ArgAction, ArgMatches, Command, ValueEnum, ValueHint,
 };
 use config::{builder::DefaultState, Config, ConfigBuilder, Environment, File, FileFormat};
-use miette::{Context, IntoDiagnostic};
+use miette::{miette, Context, IntoDiagnostic};
 use regex::Regex;
 use serde::{de::Visitor, Deserialize};
 
 use crate::{
     my_regex,
     result::Result,
-    types::{Bitrate, Extension},
+    types::{
+        Bitrate, Codec, Extension, LoudnessTarget, PathTemplate, Tag, TitleCleanup, TAG_LIST,
+        TITLE_CASE_LIST,
+    },
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
@@ -20,21 +25,94 @@ use crate::{
 pub enum Split {
     Full,
     Clips,
+    /// Create clips based on the timestamps, or on the silences if there are none
+    Silence,
+    /// Keep the entire video in addition to the clips based on the timestamps
+    Both,
 }
 
 impl ValueEnum for Split {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Split::Full, Split::Clips]
+        &[Split::Full, Split::Clips, Split::Silence, Split::Both]
     }
 
     fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
         Some(match self {
             Split::Full => PossibleValue::new("full"),
-            Split::Clips => PossibleValue::new("slow"),
+            Split::Clips => PossibleValue::new("clips"),
+            Split::Silence => PossibleValue::new("silence"),
+            Split::Both => PossibleValue::new("both"),
         })
     }
 }
 
+/// A source from which the video timestamps can be extracted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampSource {
+    /// The chapters defined on the video
+    Chapters,
+    /// The video description, parsed with the clip regexes
+    Description,
+    /// The pinned comment, or else the top comment with enough timestamps,
+    /// parsed with the clip regexes
+    Comments,
+}
+
+const TIMESTAMP_SOURCE_LIST: &[&str] = &["chapters", "description", "comments"];
+
+/// A format in which to write the tracklist of the whole-video outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tracklist {
+    /// A CUE sheet next to the output file
+    Cue,
+    /// Chapters embedded in the output file
+    Chapters,
+}
+
+const TRACKLIST_LIST: &[&str] = &["cue", "chapters"];
+
+/// Whether and how to embed the video thumbnail as cover art
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverArt {
+    /// Do not embed any cover art
+    None,
+    /// Embed the entire thumbnail
+    Full,
+    /// Embed the thumbnail cropped to a centered square
+    Square,
+}
+
+const COVER_ART_LIST: &[&str] = &["none", "full", "square"];
+
+/// How to normalize the loudness of the output files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalize {
+    /// Normalize with the two-pass `ffmpeg` `loudnorm` filter
+    Loudnorm,
+    /// Do not modify the audio, only write ReplayGain tags for the players to apply
+    ReplayGain,
+    /// Do not normalize, only re-encode the audio if its codec is not the wanted one
+    None,
+}
+
+const NORMALIZE_LIST: &[&str] = &["loudnorm", "replaygain", "none"];
+
+/// On which part of the video the loudness is normalized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoudnormScope {
+    /// Normalize every clip on its own
+    Clip,
+    /// Apply the same gain to every clip, measured on the entire video
+    Album,
+}
+
+const LOUDNORM_SCOPE_LIST: &[&str] = &["clip", "album"];
+
 #[derive(Debug)]
 pub struct TracingLevel(pub tracing::Level);
 
@@ -77,20 +155,51 @@ impl<'de> Deserialize<'de> for TracingLevel {
 pub struct AppArgs {
     pub ids: Vec<String>,
     pub clip_regex: Vec<Regex>,
+    pub timestamp_source: Vec<TimestampSource>,
+    pub detect_tracklist: bool,
+    pub max_comments: u16,
+    pub comment_min_timestamps: usize,
+    pub parse_artist: bool,
+    pub title_cleanup: TitleCleanup,
+    pub tracklist: Vec<Tracklist>,
     pub out: PathBuf,
+    pub out_template: PathTemplate,
+    pub ascii_filenames: bool,
     pub cache: PathBuf,
+    pub overrides_dir: Option<PathBuf>,
+    pub stream_store: Option<PathBuf>,
+    /// The maximum size of the stream store in MiB, or 0 if unlimited
+    pub stream_store_max_size: f64,
+    /// The maximum age of the stored streams in days, or 0 if unlimited
+    pub stream_store_max_age: f64,
     pub split: Split,
+    pub split_min_gap: f64,
+    pub split_min_length: f64,
     pub ext: Extension,
+    pub codec: Codec,
     pub shuffle: bool,
     pub cores: usize,
     pub log: TracingLevel,
     pub bitrate: Bitrate,
+    pub normalize: Normalize,
+    pub loudness: LoudnessTarget,
+    pub loudnorm_scope: LoudnormScope,
+    pub fade_in: f64,
+    pub fade_out: f64,
+    pub trim_silence: bool,
+    pub silence_threshold: f64,
+    pub silence_min_duration: f64,
+    pub snap_window: f64,
+    pub tag: Vec<Tag>,
+    pub cover_art: CoverArt,
 }
 
 pub fn parse_cli() -> Result<AppArgs> {
     // Parse the command line arguments
     let clap_args = clap_app().get_matches();
 
+    let default_loudness = LoudnessTarget::default();
+
     // Read the configuration file & environment
     let mut builder = Config::builder()
         .add_source(
@@ -106,27 +215,130 @@ pub fn parse_cli() -> Result<AppArgs> {
                 .try_parsing(true)
                 .list_separator("<~>")
                 .with_list_parse_key("id")
-                .with_list_parse_key("clip_regex"),
+                .with_list_parse_key("clip_regex")
+                .with_list_parse_key("timestamp_source")
+                .with_list_parse_key("tracklist")
+                .with_list_parse_key("tag"),
         )
         .set_default("ext", "ogg")
         .into_diagnostic()?
         .set_default("cores", 0)
         .into_diagnostic()?
+        .set_default("shuffle", false)
+        .into_diagnostic()?
+        .set_default("detect_tracklist", true)
+        .into_diagnostic()?
+        .set_default("max_comments", 100)
+        .into_diagnostic()?
+        .set_default("comment_min_timestamps", 3)
+        .into_diagnostic()?
+        .set_default("parse_artist", false)
+        .into_diagnostic()?
+        .set_default("title_case", "legacy")
+        .into_diagnostic()?
+        .set_default("title_replace", Vec::<Vec<String>>::new())
+        .into_diagnostic()?
+        .set_default("title_remove_noise", false)
+        .into_diagnostic()?
         .set_default("log", "INFO")
         .into_diagnostic()?
         .set_default("bitrate", 96)
+        .into_diagnostic()?
+        .set_default("normalize", "loudnorm")
+        .into_diagnostic()?
+        .set_default("loudnorm_i", default_loudness.integrated)
+        .into_diagnostic()?
+        .set_default("loudnorm_lra", default_loudness.range)
+        .into_diagnostic()?
+        .set_default("loudnorm_tp", default_loudness.true_peak)
+        .into_diagnostic()?
+        .set_default("loudnorm_scope", "clip")
+        .into_diagnostic()?
+        .set_default("fade_in", 0.0)
+        .into_diagnostic()?
+        .set_default("fade_out", 0.0)
+        .into_diagnostic()?
+        .set_default("trim_silence", false)
+        .into_diagnostic()?
+        .set_default("stream_store_max_size", 0.0)
+        .into_diagnostic()?
+        .set_default("stream_store_max_age", 0.0)
+        .into_diagnostic()?
+        .set_default("silence_threshold", -50.0)
+        .into_diagnostic()?
+        .set_default("silence_min_duration", 0.1)
+        .into_diagnostic()?
+        .set_default("snap_window", 0.0)
+        .into_diagnostic()?
+        .set_default("split_min_gap", 2.0)
+        .into_diagnostic()?
+        .set_default("split_min_length", 30.0)
+        .into_diagnostic()?
+        .set_default("timestamp_source", vec!["chapters", "description"])
+        .into_diagnostic()?
+        .set_default("tracklist", Vec::<String>::new())
+        .into_diagnostic()?
+        .set_default("out_template", "{clip_title}.{ext}")
+        .into_diagnostic()?
+        .set_default("ascii_filenames", false)
+        .into_diagnostic()?
+        .set_default("tag", TAG_LIST.to_vec())
+        .into_diagnostic()?
+        .set_default("cover_art", "none")
         .into_diagnostic()?;
 
     override_list::<String>(&mut builder, &clap_args, "id")?;
     override_list::<String>(&mut builder, &clap_args, "clip_regex")?;
+    override_list::<String>(&mut builder, &clap_args, "timestamp_source")?;
+    override_list::<String>(&mut builder, &clap_args, "tracklist")?;
+    override_single::<bool>(&mut builder, &clap_args, "detect_tracklist")?;
+    override_single::<u16>(&mut builder, &clap_args, "max_comments")?;
+    override_single::<u16>(&mut builder, &clap_args, "comment_min_timestamps")?;
+    override_single::<bool>(&mut builder, &clap_args, "parse_artist")?;
+    override_single::<String>(&mut builder, &clap_args, "title_case")?;
+    if let Some(vals) = clap_args.get_many::<String>("title_replace") {
+        // The regexes and their replacements are given one after the other
+        let pairs: Vec<Vec<String>> = vals
+            .cloned()
+            .collect::<Vec<_>>()
+            .chunks(2)
+            .map(<[_]>::to_vec)
+            .collect();
+        builder = builder
+            .set_override("title_replace", pairs)
+            .into_diagnostic()?;
+    }
+    override_single::<bool>(&mut builder, &clap_args, "title_remove_noise")?;
     override_single::<String>(&mut builder, &clap_args, "out")?;
+    override_single::<String>(&mut builder, &clap_args, "out_template")?;
+    override_single::<bool>(&mut builder, &clap_args, "ascii_filenames")?;
     override_single::<String>(&mut builder, &clap_args, "cache")?;
+    override_single::<String>(&mut builder, &clap_args, "overrides_dir")?;
+    override_single::<String>(&mut builder, &clap_args, "stream_store")?;
+    override_single::<f64>(&mut builder, &clap_args, "stream_store_max_size")?;
+    override_single::<f64>(&mut builder, &clap_args, "stream_store_max_age")?;
     override_single::<String>(&mut builder, &clap_args, "split")?;
+    override_single::<f64>(&mut builder, &clap_args, "split_min_gap")?;
+    override_single::<f64>(&mut builder, &clap_args, "split_min_length")?;
     override_single::<String>(&mut builder, &clap_args, "ext")?;
+    override_single::<String>(&mut builder, &clap_args, "codec")?;
     override_single::<bool>(&mut builder, &clap_args, "shuffle")?;
     override_single::<u16>(&mut builder, &clap_args, "cores")?;
     override_single::<String>(&mut builder, &clap_args, "log")?;
     override_single::<u16>(&mut builder, &clap_args, "bitrate")?;
+    override_single::<String>(&mut builder, &clap_args, "normalize")?;
+    override_single::<f64>(&mut builder, &clap_args, "loudnorm_i")?;
+    override_single::<f64>(&mut builder, &clap_args, "loudnorm_lra")?;
+    override_single::<f64>(&mut builder, &clap_args, "loudnorm_tp")?;
+    override_single::<String>(&mut builder, &clap_args, "loudnorm_scope")?;
+    override_single::<f64>(&mut builder, &clap_args, "fade_in")?;
+    override_single::<f64>(&mut builder, &clap_args, "fade_out")?;
+    override_single::<bool>(&mut builder, &clap_args, "trim_silence")?;
+    override_single::<f64>(&mut builder, &clap_args, "silence_threshold")?;
+    override_single::<f64>(&mut builder, &clap_args, "silence_min_duration")?;
+    override_single::<f64>(&mut builder, &clap_args, "snap_window")?;
+    override_list::<String>(&mut builder, &clap_args, "tag")?;
+    override_single::<String>(&mut builder, &clap_args, "cover_art")?;
 
     let config = builder.build().into_diagnostic()?;
 
@@ -143,17 +355,143 @@ pub fn parse_cli() -> Result<AppArgs> {
         Err(e) => return Err(e).into_diagnostic()?,
     };
 
+    let title_cleanup = TitleCleanup {
+        case: config.get("title_case").into_diagnostic()?,
+        replacements: config
+            .get::<Vec<(String, String)>>("title_replace")
+            .into_diagnostic()?
+            .into_iter()
+            .map(|(re, replacement)| {
+                let re = Regex::new(&re)
+                    .into_diagnostic()
+                    .wrap_err("Error while parsing title replacement regex")?;
+                Ok((re, replacement))
+            })
+            .collect::<Result<_>>()?,
+        remove_noise: config.get("title_remove_noise").into_diagnostic()?,
+    };
+
+    let overrides_dir = match config.get::<PathBuf>("overrides_dir") {
+        Ok(dir) => Some(dir),
+        Err(config::ConfigError::NotFound(_)) => None,
+        Err(e) => return Err(e).into_diagnostic()?,
+    };
+
+    let stream_store = match config.get::<PathBuf>("stream_store") {
+        Ok(dir) => Some(dir),
+        Err(config::ConfigError::NotFound(_)) => None,
+        Err(e) => return Err(e).into_diagnostic()?,
+    };
+    let stream_store_max_size: f64 = config.get("stream_store_max_size").into_diagnostic()?;
+    let stream_store_max_age: f64 = config.get("stream_store_max_age").into_diagnostic()?;
+    if stream_store_max_size < 0.0 || stream_store_max_age < 0.0 {
+        return Err(miette!("The stream store maximum size and age must not be negative").into());
+    }
+
+    let out_template = config
+        .get::<String>("out_template")
+        .into_diagnostic()?
+        .parse()
+        .wrap_err("Error while parsing output template")?;
+
+    let ext: Extension = config.get("ext").into_diagnostic()?;
+    let codec = match config.get::<Codec>("codec") {
+        Ok(codec) => codec,
+        Err(config::ConfigError::NotFound(_)) => Codec::default_for(ext),
+        Err(e) => return Err(e).into_diagnostic()?,
+    };
+    if !codec.supported_extensions().contains(&ext) {
+        let supported: Vec<_> = codec
+            .supported_extensions()
+            .iter()
+            .map(|e| e.with_no_dot())
+            .collect();
+        return Err(miette!(
+            "The {} codec cannot be stored in {} files, expected one of: {}",
+            codec.name(),
+            ext.with_no_dot(),
+            supported.join(", ")
+        )
+        .into());
+    }
+
+    let cover_art: CoverArt = config.get("cover_art").into_diagnostic()?;
+    if cover_art != CoverArt::None && ext == Extension::Webm {
+        return Err(miette!(
+            "Cover art cannot be embedded in webm files, use the mka or mkv extension instead"
+        )
+        .into());
+    }
+
+    let loudness = LoudnessTarget::new(
+        config.get("loudnorm_i").into_diagnostic()?,
+        config.get("loudnorm_lra").into_diagnostic()?,
+        config.get("loudnorm_tp").into_diagnostic()?,
+    )
+    .wrap_err("Invalid loudness normalization target")?;
+
+    let fade_in: f64 = config.get("fade_in").into_diagnostic()?;
+    let fade_out: f64 = config.get("fade_out").into_diagnostic()?;
+    let silence_threshold: f64 = config.get("silence_threshold").into_diagnostic()?;
+    let silence_min_duration: f64 = config.get("silence_min_duration").into_diagnostic()?;
+    let snap_window: f64 = config.get("snap_window").into_diagnostic()?;
+    let split_min_gap: f64 = config.get("split_min_gap").into_diagnostic()?;
+    let split_min_length: f64 = config.get("split_min_length").into_diagnostic()?;
+    if [
+        fade_in,
+        fade_out,
+        silence_min_duration,
+        snap_window,
+        split_min_gap,
+        split_min_length,
+    ]
+    .iter()
+    .any(|&duration| duration < 0.0)
+    {
+        return Err(miette!("Fade, silence, snap and split durations must not be negative").into());
+    }
+    if silence_threshold > 0.0 {
+        return Err(miette!("The silence threshold must be negative, in dB").into());
+    }
+
     Ok(AppArgs {
         ids: config.get("id").into_diagnostic()?,
         clip_regex,
+        timestamp_source: config.get("timestamp_source").into_diagnostic()?,
+        detect_tracklist: config.get("detect_tracklist").into_diagnostic()?,
+        max_comments: config.get("max_comments").into_diagnostic()?,
+        comment_min_timestamps: config.get("comment_min_timestamps").into_diagnostic()?,
+        tracklist: config.get("tracklist").into_diagnostic()?,
+        parse_artist: config.get("parse_artist").into_diagnostic()?,
+        title_cleanup,
         out: config.get("out").into_diagnostic()?,
+        out_template,
+        ascii_filenames: config.get("ascii_filenames").into_diagnostic()?,
         cache: config.get("cache").into_diagnostic()?,
+        overrides_dir,
+        stream_store,
+        stream_store_max_size,
+        stream_store_max_age,
         split: config.get("split").into_diagnostic()?,
-        ext: config.get("ext").into_diagnostic()?,
+        split_min_gap,
+        split_min_length,
+        ext,
+        codec,
         shuffle: config.get("shuffle").into_diagnostic()?,
         cores: config.get("cores").into_diagnostic()?,
         log: config.get("log").into_diagnostic()?,
         bitrate: config.get("bitrate").into_diagnostic()?,
+        normalize: config.get("normalize").into_diagnostic()?,
+        loudness,
+        loudnorm_scope: config.get("loudnorm_scope").into_diagnostic()?,
+        fade_in,
+        fade_out,
+        trim_silence: config.get("trim_silence").into_diagnostic()?,
+        silence_threshold,
+        silence_min_duration,
+        snap_window,
+        tag: config.get("tag").into_diagnostic()?,
+        cover_art,
     })
 }
 
@@ -206,6 +544,11 @@ where
         },
     };
 
+    // Flags have a default value, which must not override the configuration file
+    if clap_args.value_source(id) == Some(ValueSource::DefaultValue) {
+        return Ok(());
+    }
+
     if let Some(val) = arg_opt {
         *builder = std::mem::take(builder)
             .set_override(id, val.clone())
@@ -231,6 +574,18 @@ fn arg_bool(name: &'static str) -> Arg {
     arg_base(name).action(ArgAction::SetTrue)
 }
 
+fn arg_number(name: &'static str) -> Arg {
+    arg_single(name)
+        .value_parser(value_parser!(f64))
+        .allow_negative_numbers(true)
+}
+
+/// Parse the possible values of an enum as strings,
+/// so that they can be given to the config builder
+fn possible_values<E: ValueEnum>() -> PossibleValuesParser {
+    PossibleValuesParser::new(E::value_variants().iter().filter_map(E::to_possible_value))
+}
+
 fn clap_app() -> Command {
     command!()
         .arg(
@@ -245,24 +600,87 @@ fn clap_app() -> Command {
                 .value_hint(ValueHint::DirPath)
                 .help(help::OUT),
         )
+        .arg(arg_single("out_template").help(help::OUT_TEMPLATE))
+        .arg(arg_bool("ascii_filenames").help(help::ASCII_FILENAMES))
         .arg(
             arg_single("cache")
                 .value_hint(ValueHint::DirPath)
                 .help(help::CACHE),
         )
+        .arg(
+            arg_single("overrides_dir")
+                .value_hint(ValueHint::DirPath)
+                .help(help::OVERRIDES_DIR),
+        )
+        .arg(
+            arg_single("stream_store")
+                .value_hint(ValueHint::DirPath)
+                .help(help::STREAM_STORE),
+        )
+        .arg(arg_number("stream_store_max_size").help(help::STREAM_STORE_MAX_SIZE))
+        .arg(arg_number("stream_store_max_age").help(help::STREAM_STORE_MAX_AGE))
         .arg(
             arg_single("split")
-                .value_parser(value_parser!(Split))
+                .value_parser(possible_values::<Split>())
                 .ignore_case(true)
                 .help(help::SPLIT),
         )
+        .arg(arg_number("split_min_gap").help(help::SPLIT_MIN_GAP))
+        .arg(arg_number("split_min_length").help(help::SPLIT_MIN_LENGTH))
         .arg(
             arg_single("ext")
-                .value_parser(value_parser!(Extension))
+                .value_parser(possible_values::<Extension>())
                 .ignore_case(true)
                 .help(help::EXT),
         )
+        .arg(
+            arg_single("codec")
+                .value_parser(possible_values::<Codec>())
+                .ignore_case(true)
+                .help(help::CODEC),
+        )
         .arg(arg_list("clip_regex").help(help::CLIP_REGEX))
+        .arg(
+            arg_list("timestamp_source")
+                .value_parser(PossibleValuesParser::new(TIMESTAMP_SOURCE_LIST))
+                .ignore_case(true)
+                .help(help::TIMESTAMP_SOURCE),
+        )
+        .arg(
+            arg_single("max_comments")
+                .value_parser(value_parser!(u16))
+                .help(help::MAX_COMMENTS),
+        )
+        .arg(
+            arg_single("comment_min_timestamps")
+                .value_parser(value_parser!(u16))
+                .help(help::COMMENT_MIN_TIMESTAMPS),
+        )
+        .arg(
+            arg_single("detect_tracklist")
+                .value_parser(value_parser!(bool))
+                .help(help::DETECT_TRACKLIST),
+        )
+        .arg(
+            arg_list("tracklist")
+                .value_parser(PossibleValuesParser::new(TRACKLIST_LIST))
+                .ignore_case(true)
+                .help(help::TRACKLIST),
+        )
+        .arg(arg_bool("parse_artist").help(help::PARSE_ARTIST))
+        .arg(
+            arg_single("title_case")
+                .value_parser(PossibleValuesParser::new(TITLE_CASE_LIST))
+                .ignore_case(true)
+                .help(help::TITLE_CASE),
+        )
+        .arg(
+            arg_list("title_replace")
+                .num_args(2)
+                .value_names(["REGEX", "REPLACEMENT"])
+                .help(help::TITLE_REPLACE),
+        )
+        .arg(arg_bool("title_remove_noise").help(help::TITLE_REMOVE_NOISE))
         .arg(arg_bool("shuffle").help(help::SHUFFLE))
         .arg(arg_single("cores").help(help::CORES))
         .arg(
@@ -272,22 +690,112 @@ fn clap_app() -> Command {
                 .help(help::LOG),
         )
         .arg(arg_single("bitrate").help(help::BITRATE))
+        .arg(
+            arg_single("normalize")
+                .value_parser(PossibleValuesParser::new(NORMALIZE_LIST))
+                .ignore_case(true)
+                .help(help::NORMALIZE),
+        )
+        .arg(arg_number("loudnorm_i").help(help::LOUDNORM_I))
+        .arg(arg_number("loudnorm_lra").help(help::LOUDNORM_LRA))
+        .arg(arg_number("loudnorm_tp").help(help::LOUDNORM_TP))
+        .arg(
+            arg_single("loudnorm_scope")
+                .value_parser(PossibleValuesParser::new(LOUDNORM_SCOPE_LIST))
+                .ignore_case(true)
+                .help(help::LOUDNORM_SCOPE),
+        )
+        .arg(arg_number("fade_in").help(help::FADE_IN))
+        .arg(arg_number("fade_out").help(help::FADE_OUT))
+        .arg(arg_bool("trim_silence").help(help::TRIM_SILENCE))
+        .arg(arg_number("silence_threshold").help(help::SILENCE_THRESHOLD))
+        .arg(arg_number("silence_min_duration").help(help::SILENCE_MIN_DURATION))
+        .arg(arg_number("snap_window").help(help::SNAP_WINDOW))
+        .arg(
+            arg_list("tag")
+                .value_parser(PossibleValuesParser::new(TAG_LIST))
+                .ignore_case(true)
+                .help(help::TAG),
+        )
+        .arg(
+            arg_single("cover_art")
+                .value_parser(PossibleValuesParser::new(COVER_ART_LIST))
+                .ignore_case(true)
+                .help(help::COVER_ART),
+        )
 }
 
 mod help {
     pub const CONFIG: &str = "The path to the TOML config file";
     pub const ID: &str = "The IDs of playlists or videos";
     pub const OUT: &str = "The path to the output directory";
+    pub const OUT_TEMPLATE: &str = indoc::indoc! {"
+        The template of the output file paths, relative to the output directory.
+        Directories are separated by `/` and created as needed.
+
+        Available variables: `{uploader}`, `{video_title}`, `{video_id}`, `{track}`, `{clip_title}`, `{artist}`, `{ext}`.
+        `{artist}` is the clip artist, or the video uploader if unknown.
+        Numeric variables can be zero-padded, e.g. `{track:02}`
+    "};
+    pub const ASCII_FILENAMES: &str = indoc::indoc! {"
+        Transliterate the output file paths to ASCII, e.g. `Café` to `Cafe`.
+
+        The characters that are invalid on Windows are always removed from the paths,
+        and the file names are truncated to 255 bytes
+    "};
     pub const CACHE: &str =
         "The path to the cache file, avoiding processing multiple times the same videos";
-    pub const SPLIT: &str =
-        "Either keep the entire video or create clips based on timestamps in the description";
+    pub const OVERRIDES_DIR: &str = indoc::indoc! {"
+        The path to a directory of files overriding the timestamps of videos.
+
+        `<video_id>.cue` (CUE sheet) or `<video_id>.txt` (same formats as the description) is used
+        instead of the timestamp sources. When the file is added or changed, the video is processed again,
+        without deleting its previous clips
+    "};
+    pub const STREAM_STORE: &str = indoc::indoc! {"
+        The path to a directory keeping the downloaded streams between runs, dedicated to them.
+
+        The videos already processed are still skipped. A video processed again, e.g. after it failed,
+        after deleting it from the `videos` table of the cache or changing its timestamps override,
+        is then clipped without being downloaded again
+    "};
+    pub const STREAM_STORE_MAX_SIZE: &str = indoc::indoc! {"
+        The maximum size of the stream store, in MiB. 0 for no limit.
+
+        The least recently used streams are deleted after each run to fit in it
+    "};
+    pub const STREAM_STORE_MAX_AGE: &str =
+        "The number of days after which an unused stream is deleted from the stream store. 0 for no limit";
+    pub const SPLIT: &str = indoc::indoc! {"
+        Either keep the entire video or create clips based on timestamps in the description.
+
+        `silence` also creates clips based on timestamps, but when a video has none,
+        splits it on the silences longer than `split_min_gap`, with `silence_threshold` as the silence volume.
+        The clips are named `<video title> - Part NN`.
+
+        `both` keeps the entire video in addition to the clips based on timestamps
+    "};
+    pub const SPLIT_MIN_GAP: &str =
+        "With `split = \"silence\"`, the minimum duration of a silence separating two clips, in seconds";
+    pub const SPLIT_MIN_LENGTH: &str =
+        "With `split = \"silence\"`, the minimum duration of a clip, in seconds";
     pub const EXT: &str =
         "The file extension to use for the output files. Defines the file container format to use";
+    pub const CODEC: &str = indoc::indoc! {"
+        The audio codec to encode the output files with. Must be supported by the container:
+        opus: ogg, opus, mka, mkv, webm
+        vorbis: ogg, mka, mkv, webm
+        mp3: mp3, mka, mkv
+        aac: m4a, mka, mkv
+        flac: flac, ogg, mka, mkv
+        Defaults to mp3 in mp3 files, flac in flac files, aac in m4a files and opus otherwise
+    "};
 
     pub const CLIP_REGEX: &str = indoc::indoc! {"
         Regular expressions to extract timestamps from description.
         Must capture `time` and `title` groups (starting timestamp & clip title).
+        May capture an `end` group (ending timestamp), otherwise the clip ends at the next timestamp.
+        May capture an `artist` group (clip artist), otherwise the video uploader is used.
         
         For every description line, every pattern will be tested until one matches.
         A default pattern that should handle most cases is used if none is provided.
@@ -295,6 +803,60 @@ mod help {
         Must use the [Regex crate syntax](https://docs.rs/regex/latest/regex/#syntax)
     "};
 
+    pub const TIMESTAMP_SOURCE: &str = indoc::indoc! {"
+        The sources from which to extract the timestamps, in order of priority.
+        The first source giving at least one timestamp is used.
+
+        `comments` uses the comment written & pinned by the uploader, or else the most-liked comment
+        with at least `comment_min_timestamps` timestamps. It is not used by default.
+
+        If no source gives any timestamp, the entire video is kept as one clip
+    "};
+
+    pub const MAX_COMMENTS: &str =
+        "With the `comments` timestamp source, the maximum number of top comments to fetch (yt-dlp only)";
+    pub const COMMENT_MIN_TIMESTAMPS: &str = indoc::indoc! {"
+        With the `comments` timestamp source, the minimum number of timestamps
+        of the top comment used when the pinned comment has none
+    "};
+    pub const DETECT_TRACKLIST: &str = indoc::indoc! {"
+        Only use the tracklist block of the descriptions, ignoring the stray timestamps around it
+        (e.g. sponsor lines, stream schedules).
+
+        The block is the longest run of consecutive lines matching the clip regexes,
+        or the first run after a header line such as `Tracklist:`
+    "};
+
+    pub const PARSE_ARTIST: &str = indoc::indoc! {"
+        Split the clip titles into their artist and title, on `Artist - Title` or `Title by Artist`.
+        A single word before `by` is kept in the title, e.g. `Stand by Me`.
+        The `artist` group of the clip regexes is used instead if captured
+    "};
+    pub const TITLE_CASE: &str = indoc::indoc! {"
+        How to change the case of the video & clip titles.
+
+        `legacy` (default) keeps the behaviour of the previous versions: the characters like `/` or `#`
+        are removed and the clip titles are title-cased, e.g. `AC/DC` into `Ac Dc`.
+        The other values also normalize the titles and collapse their whitespaces.
+        `title` capitalizes the words that are entirely in lowercase, keeping e.g. `iPhone` or `AC/DC` as-is
+    "};
+    pub const TITLE_REPLACE: &str = indoc::indoc! {"
+        Replace the matches of a regex in the video & clip titles, e.g. `--title_replace '\\s*\\|.*' ''`.
+        The replacement may refer to the regex groups, e.g. `$1`.
+        The rules are applied in order. In the config file: `title_replace = [[\"<regex>\", \"<replacement>\"]]`
+    "};
+    pub const TITLE_REMOVE_NOISE: &str = indoc::indoc! {"
+        Remove the bracketed noise from the video & clip titles, e.g. `[Official Video]`, `(Lyrics)` or `【MV】`
+    "};
+    pub const TRACKLIST: &str = indoc::indoc! {"
+        With `split = \"full\"` or `\"both\"`, the formats in which to write the video timestamps
+        along with the entire video output file,
+        so that players can navigate between the tracks.
+
+        `cue` writes a CUE sheet next to the output file, `chapters` embeds the timestamps as chapters
+        (`CHAPTERxxx` comments in ogg and flac files)
+    "};
+
     pub const SHUFFLE: &str = "Randomize the order in which the videos are downloaded. Do not influence how clips are processed";
     pub const CORES: &str = indoc::indoc! {"
         Assume the machine has this number of cores. Used to modify the number of worker threads spawned.
@@ -304,4 +866,52 @@ mod help {
     pub const LOG: &str = "The logging level to use";
     pub const BITRATE: &str =
         "The audio bitrate to use for output files. Must follow the `ffmpeg` bitrate format";
+    pub const NORMALIZE: &str = indoc::indoc! {"
+        How to normalize the loudness of the output files.
+
+        `loudnorm` uses the two-pass EBU R128 `ffmpeg` filter with the `loudnorm_*` targets.
+        `replaygain` keeps the audio as-is but writes the track & album (video) gains as tags,
+        `R128_*_GAIN` for opus and `REPLAYGAIN_*` for other codecs.
+        `none` keeps the audio as-is, only re-encoding it when the source codec is not the wanted one
+    "};
+    pub const LOUDNORM_I: &str = "The integrated loudness target, in LUFS, between -70 and -5";
+    pub const LOUDNORM_LRA: &str = "The loudness range target, in LU, between 1 and 50";
+    pub const LOUDNORM_TP: &str = "The maximum true peak, in dBTP, between -9 and 0";
+    pub const LOUDNORM_SCOPE: &str = indoc::indoc! {"
+        With `normalize = \"loudnorm\"`, either normalize every clip on its own,
+        or measure the loudness on the entire video and apply the same gain to all its clips,
+        keeping the loudness differences between them.
+
+        The album gain is limited so that the true peak of the video does not exceed the target
+    "};
+    pub const FADE_IN: &str = "The duration of the fade-in at the start of every clip, in seconds";
+    pub const FADE_OUT: &str = "The duration of the fade-out at the end of every clip, in seconds";
+    pub const TRIM_SILENCE: &str = indoc::indoc! {"
+        Remove the silence at the start and the end of every clip.
+
+        The end of a clip is removed from its first silence lasting at least `split_min_gap` seconds.
+        The fade-out is applied before, so it is removed along with a long enough trailing silence
+    "};
+    pub const SILENCE_THRESHOLD: &str =
+        "The volume, in dB, under which the audio is considered as silence, to trim or split clips";
+    pub const SILENCE_MIN_DURATION: &str = indoc::indoc! {"
+        The minimum duration, in seconds, of a sound ending a silence.
+        Shorter sounds (clicks, crackles) are considered part of the silence
+    "};
+    pub const SNAP_WINDOW: &str = indoc::indoc! {"
+        Move every clip boundary to the quietest point at most this number of seconds around it,
+        so that clips do not start or end in the middle of a sound.
+
+        When using a value of 0 (default), the boundaries are kept as given by the timestamps
+    "};
+    pub const TAG: &str = indoc::indoc! {"
+        The metadata tags to write to the output files.
+
+        `title` is the clip title, `artist` the clip artist or else the video uploader, `album` the video title and ID,
+        `track` the clip number in the video, `date` the video upload date and `comment` the video URL
+    "};
+    pub const COVER_ART: &str = indoc::indoc! {"
+        Embed the video thumbnail as cover art in the output files, either entirely or cropped to a square.
+        Not supported in webm files
+    "};
 }
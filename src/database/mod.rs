@@ -1,9 +1,11 @@
 mod sqlite;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use miette::Result;
 
+use crate::types::{Comment, LoudnessMeasure, Timestamp};
+
 pub use sqlite::Sqlite;
 
 pub type ClipIdx = u16;
@@ -31,6 +33,41 @@ pub enum ProcessedState {
 
     /// The video has been entirely processed
     Completed,
+
+    /// The video could not be downloaded or its metadata could not be extracted.
+    /// It will be processed again from scratch on the next run.
+    Failed,
+}
+
+/// A video or clip whose processing has failed
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// The video string ID
+    pub video_id: String,
+
+    /// The index of the clip that failed, or `None` if it is the entire video that failed
+    pub clip_idx: Option<ClipIdx>,
+
+    /// The error message of the last failure
+    pub error: String,
+
+    /// The number of times the processing has been attempted and failed
+    pub attempts: u32,
+}
+
+/// A downloaded stream kept in the stream store
+#[derive(Debug, Clone)]
+pub struct StoredStream {
+    pub path: PathBuf,
+
+    /// The size of the stream file, in bytes
+    pub size: u64,
+
+    /// The SHA-256 hash of the stream file
+    pub hash: String,
+
+    /// When the stream was last used, in seconds since the Unix epoch
+    pub last_used: u64,
 }
 
 /// A trait for saving useful application data between multiple executions.
@@ -65,9 +102,29 @@ where
     /// The indexes are zero-based (0 to len-1).
     /// The indexes may not be completed in order.
     ///
+    /// The path of the created file is recorded, along with a description of
+    /// the audio `settings` used to create it (normalization, codec, ...).
+    ///
     /// Once all units of work have been completed, the database **may**
     /// internally mark the video as fully completed or wait for a call to [`set_video_as_completed`].
-    fn complete_work(&self, video: VideoId, clip_idx: ClipIdx) -> Result<()>;
+    fn complete_work(
+        &self,
+        video: VideoId,
+        clip_idx: ClipIdx,
+        output: &Path,
+        settings: &str,
+    ) -> Result<()>;
+
+    /// Inform the database that the clip with the specified index
+    /// could not be processed, along with the reason why.
+    ///
+    /// The clip **must** remain in the work to do, so that it can be retried
+    /// on a later run.
+    fn fail_work(&self, video: VideoId, clip_idx: ClipIdx, error: &str) -> Result<()>;
+
+    /// Count the number of clips of the video that have not been completed,
+    /// including the failed ones.
+    fn count_remaining_work(&self, video: VideoId) -> Result<usize>;
 
     /// Inform the database that all the work for the video has been done.
     ///
@@ -75,8 +132,80 @@ where
     /// to be completed to the database.
     fn set_video_as_completed(&self, video: VideoId) -> Result<()>;
 
+    /// Inform the database that the video could not be processed,
+    /// along with the reason why.
+    ///
+    /// The work previously assigned to the video is kept, so that only
+    /// its remaining clips are processed on the next attempt.
+    fn set_video_as_failed(&self, video: VideoId, error: &str) -> Result<()>;
+
     /// Count the number of videos in the database.
     ///
     /// If a filter is specified, only count those that are in the given state.
     fn count_videos(&self, filter: Option<ProcessedState>) -> Result<usize>;
+
+    /// List every video and clip whose last processing attempt has failed.
+    fn failures(&self) -> Result<Vec<Failure>>;
+
+    /// Get the loudness previously measured on the stream of the video,
+    /// between the timestamp bounds or on the entire stream if `None`.
+    ///
+    /// The measure is only returned if the stream file had the same hash.
+    fn loudness(
+        &self,
+        video: VideoId,
+        timestamp: Option<&Timestamp>,
+        file_hash: &str,
+    ) -> Result<Option<LoudnessMeasure>>;
+
+    /// Save the loudness measured on the stream of the video,
+    /// between the timestamp bounds or on the entire stream if `None`.
+    ///
+    /// The measures made on a stream file with another hash **may** be discarded.
+    fn set_loudness(
+        &self,
+        video: VideoId,
+        timestamp: Option<&Timestamp>,
+        file_hash: &str,
+        measure: &LoudnessMeasure,
+    ) -> Result<()>;
+
+    /// Get the hash of the timestamps override file used for the video, if any.
+    fn override_hash(&self, video: VideoId) -> Result<Option<String>>;
+
+    /// Reset the progress of the video so that it is entirely processed again,
+    /// and record the hash of the timestamps override file now used for it.
+    ///
+    /// Return the paths of the clips previously created for the video,
+    /// which are forgotten by the database but not deleted.
+    fn reset_video(&self, video: VideoId, override_hash: Option<&str>) -> Result<Vec<PathBuf>>;
+
+    /// Record the bounds of the clip with the specified index, once they have been
+    /// moved from the `original` bounds of its timestamp to the `adjusted` ones.
+    fn set_clip_bounds(
+        &self,
+        video: VideoId,
+        clip_idx: ClipIdx,
+        original: &Timestamp,
+        adjusted: &Timestamp,
+    ) -> Result<()>;
+
+    /// Record the comment from which the timestamps of the video were extracted
+    fn set_timestamps_comment(&self, video: VideoId, comment: &Comment) -> Result<()>;
+
+    /// Get the stream of the video kept in the stream store, if any.
+    ///
+    /// The streams are indexed by the video IDs rather than the cache ones,
+    /// so that they outlive the removal of their video from the cache.
+    fn stored_stream(&self, video_id: &str) -> Result<Option<StoredStream>>;
+
+    /// Get all the streams kept in the stream store with their video ID,
+    /// the least recently used first
+    fn stored_streams(&self) -> Result<Vec<(String, StoredStream)>>;
+
+    /// Record the stream of the video kept in the stream store, or update its last use
+    fn set_stored_stream(&self, video_id: &str, stream: &StoredStream) -> Result<()>;
+
+    /// Forget the stream of the video kept in the stream store
+    fn remove_stored_stream(&self, video_id: &str) -> Result<()>;
 }
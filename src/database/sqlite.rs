@@ -1,4 +1,8 @@
-use std::{fmt::Write, path::Path, sync::RwLock};
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use miette::{Context, IntoDiagnostic, Result};
 use rusqlite::{
@@ -8,32 +12,120 @@ use rusqlite::{
 };
 use tracing::debug;
 
-use super::{CacheDb, ClipIdx, ProcessedState, VideoId};
+use crate::types::{Comment, LoudnessMeasure, Timestamp};
+
+use super::{CacheDb, ClipIdx, Failure, ProcessedState, StoredStream, VideoId};
+
+/// Schema migrations applied on top of the base tables created in [`Sqlite::create_tables`].
+///
+/// The number of applied migrations is stored in the sqlite `user_version` pragma,
+/// so new migrations **must** only be appended to this list.
+const MIGRATIONS: &[&str] = &[
+    // 1: Record failures of videos & clips
+    "ALTER TABLE videos ADD COLUMN error TEXT;
+    ALTER TABLE videos ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE work ADD COLUMN error TEXT;
+    ALTER TABLE work ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;",
+    // 2: Record the created clips and the settings used to create them
+    "CREATE TABLE clips (
+        video_id    INTEGER,
+        clip_idx    INTEGER,
+        path        TEXT NOT NULL,
+        settings    TEXT NOT NULL,
+
+        PRIMARY KEY (video_id, clip_idx),
+
+        FOREIGN KEY (video_id)
+            REFERENCES videos (id)
+            ON DELETE CASCADE
+            ON UPDATE NO ACTION
+    );",
+    // 3: Cache the loudness measures of the streams & clips
+    "CREATE TABLE loudness (
+        video_id    INTEGER,
+        t_start     INTEGER NOT NULL,
+        t_end       INTEGER,
+        file_hash   TEXT NOT NULL,
+        integrated  REAL NOT NULL,
+        range       REAL NOT NULL,
+        true_peak   REAL NOT NULL,
+        threshold   REAL NOT NULL,
+
+        FOREIGN KEY (video_id)
+            REFERENCES videos (id)
+            ON DELETE CASCADE
+            ON UPDATE NO ACTION
+    );
+    CREATE INDEX loudness_video ON loudness (video_id, t_start);",
+    // 4: Record the clip bounds moved to a quiet point
+    "CREATE TABLE clip_bounds (
+        video_id        INTEGER,
+        clip_idx        INTEGER,
+        original_start  INTEGER NOT NULL,
+        original_end    INTEGER,
+        t_start         INTEGER NOT NULL,
+        t_end           INTEGER,
+
+        PRIMARY KEY (video_id, clip_idx),
+
+        FOREIGN KEY (video_id)
+            REFERENCES videos (id)
+            ON DELETE CASCADE
+            ON UPDATE NO ACTION
+    );",
+    // 5: Record the timestamps override file used for the videos
+    "ALTER TABLE videos ADD COLUMN override_hash TEXT;",
+    // 6: Record the comments from which the timestamps of the videos were extracted
+    "CREATE TABLE timestamps_comments (
+        video_id    INTEGER PRIMARY KEY,
+        comment_id  TEXT NOT NULL,
+        author      TEXT,
+        pinned      INTEGER NOT NULL,
+        like_count  INTEGER,
+        text        TEXT NOT NULL,
+
+        FOREIGN KEY (video_id)
+            REFERENCES videos (id)
+            ON DELETE CASCADE
+            ON UPDATE NO ACTION
+    );",
+    // 7: Record the downloaded streams kept in the stream store,
+    // keyed by the video IDs so that they outlive the removal of their video from the cache
+    "CREATE TABLE streams (
+        video_id    TEXT PRIMARY KEY,
+        path        TEXT NOT NULL,
+        size        INTEGER NOT NULL,
+        hash        TEXT NOT NULL,
+        last_used   INTEGER NOT NULL
+    );",
+];
 
 #[derive(Debug)]
 pub struct Sqlite {
-    conn: RwLock<Connection>,
+    conn: Mutex<Connection>,
 }
 
-unsafe impl Sync for Sqlite {}
-
 impl CacheDb for Sqlite {
     fn read_or_create(p: &Path) -> Result<Self> {
+        let conn = Connection::open(p)
+            .into_diagnostic()
+            .wrap_err("Could not open sqlite file")?;
+        // Disabled by default, and needed for the rows of a video to be deleted along with it
+        conn.pragma_update(None, "foreign_keys", true)
+            .into_diagnostic()
+            .wrap_err("Could not enable the foreign keys")?;
         let cache = Self {
-            conn: RwLock::new(
-                Connection::open(p)
-                    .into_diagnostic()
-                    .wrap_err("Could not open sqlite file")?,
-            ),
+            conn: Mutex::new(conn),
         };
 
         cache.create_tables().wrap_err("Could not create tables")?;
+        cache.migrate().wrap_err("Could not migrate tables")?;
 
         Ok(cache)
     }
 
     fn check_video(&self, video_id: &str) -> Result<(VideoId, ProcessedState)> {
-        let conn = self.conn.read().unwrap();
+        let conn = self.conn.lock().unwrap();
 
         // Try to get the corresponding row
         if let Some((id, status, work_len)) = conn
@@ -52,13 +144,13 @@ impl CacheDb for Sqlite {
 
             let status: ProcessedState = status.0;
 
-            // Simple case: no need to check more of the database
+            // Simple case: no need to check more of the database.
+            // A video that failed after being assigned work resumes it instead
             if status == ProcessedState::Completed || work_len.is_none() {
                 return Ok((id, status));
             }
 
             // Harder case: check the work to do
-            let conn = self.conn.read().unwrap();
             let mut stmt = conn
                 .prepare(
                     "SELECT clip_idx FROM work
@@ -74,9 +166,6 @@ impl CacheDb for Sqlite {
                 .collect();
             Ok((id, ProcessedState::RemainingClips(work_indexes)))
         } else {
-            drop(conn);
-            let conn = self.conn.write().unwrap();
-
             // Video not in the table, insert it and get back the id
             debug!("Video not in the table, inserting it");
             let start_state = ProcessedState::NotProcessed;
@@ -96,7 +185,7 @@ impl CacheDb for Sqlite {
     }
 
     fn assign_work(&self, video: VideoId, nb_clips: ClipIdx) -> Result<()> {
-        let conn = self.conn.write().unwrap();
+        let conn = self.conn.lock().unwrap();
 
         // Delete any previous work
         debug!("Deleting all old work of video {video}");
@@ -116,12 +205,16 @@ impl CacheDb for Sqlite {
             .into_diagnostic()
             .wrap_err("Could not insert new assigned work rows")?;
 
-        // Set the work length to the video
+        // Set the work length to the video, clearing any previous video failure
         conn.execute(
             "UPDATE videos
-            SET work_len = ?
+            SET work_len = ?, status = ?, error = NULL
             WHERE id = ?",
-            params![nb_clips, video],
+            params![
+                nb_clips,
+                SqliteProcessedState(ProcessedState::NotProcessed),
+                video
+            ],
         )
         .into_diagnostic()
         .wrap_err("Could not update video with new work length")?;
@@ -129,8 +222,14 @@ impl CacheDb for Sqlite {
         Ok(())
     }
 
-    fn complete_work(&self, video: VideoId, clip_idx: ClipIdx) -> Result<()> {
-        let conn = self.conn.write().unwrap();
+    fn complete_work(
+        &self,
+        video: VideoId,
+        clip_idx: ClipIdx,
+        output: &Path,
+        settings: &str,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
 
         debug!("Complete work {clip_idx} of video {video}");
         conn.execute(
@@ -138,17 +237,51 @@ impl CacheDb for Sqlite {
             params![video, clip_idx],
         )
         .into_diagnostic()?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO clips (video_id, clip_idx, path, settings)
+            VALUES (?, ?, ?, ?)",
+            params![video, clip_idx, output.to_string_lossy(), settings],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not record the created clip")?;
+        Ok(())
+    }
+
+    fn fail_work(&self, video: VideoId, clip_idx: ClipIdx, error: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        debug!("Fail work {clip_idx} of video {video}");
+        conn.execute(
+            "UPDATE work
+            SET error = ?, attempts = attempts + 1
+            WHERE video_id = ? AND clip_idx = ?",
+            params![error, video, clip_idx],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not set work as failed")?;
         Ok(())
     }
 
+    fn count_remaining_work(&self, video: VideoId) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT COUNT(clip_idx) FROM work WHERE video_id = ?",
+            [video],
+            |row| row.get(0),
+        )
+        .into_diagnostic()
+    }
+
     fn set_video_as_completed(&self, video: VideoId) -> Result<()> {
-        let conn = self.conn.write().unwrap();
+        let conn = self.conn.lock().unwrap();
 
         // Set as completed
         debug!("Set video {video} as completed");
         conn.execute(
             "UPDATE videos
-            SET status = ?
+            SET status = ?, error = NULL
             WHERE id = ?",
             params![SqliteProcessedState(ProcessedState::Completed), video],
         )
@@ -164,8 +297,24 @@ impl CacheDb for Sqlite {
         Ok(())
     }
 
+    fn set_video_as_failed(&self, video: VideoId, error: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        debug!("Set video {video} as failed");
+        conn.execute(
+            "UPDATE videos
+            SET status = ?, error = ?, attempts = attempts + 1
+            WHERE id = ?",
+            params![SqliteProcessedState(ProcessedState::Failed), error, video],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not set video as failed")?;
+
+        Ok(())
+    }
+
     fn count_videos(&self, filter: Option<ProcessedState>) -> Result<usize> {
-        let conn = self.conn.read().unwrap();
+        let conn = self.conn.lock().unwrap();
 
         Ok(if let Some(filter) = filter {
             conn.query_row(
@@ -179,12 +328,308 @@ impl CacheDb for Sqlite {
                 .into_diagnostic()?
         })
     }
+
+    fn failures(&self) -> Result<Vec<Failure>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT str_id, NULL, error, attempts FROM videos
+                WHERE status = ? AND error IS NOT NULL
+                UNION ALL
+                SELECT videos.str_id, work.clip_idx, work.error, work.attempts FROM work
+                INNER JOIN videos ON videos.id = work.video_id
+                WHERE work.error IS NOT NULL",
+            )
+            .into_diagnostic()?;
+
+        let failures = stmt
+            .query_map([SqliteProcessedState(ProcessedState::Failed)], |row| {
+                Ok(Failure {
+                    video_id: row.get(0)?,
+                    clip_idx: row.get(1)?,
+                    error: row.get(2)?,
+                    attempts: row.get(3)?,
+                })
+            })
+            .into_diagnostic()
+            .wrap_err("Could not query failures")?
+            .collect::<rusqlite::Result<_>>()
+            .into_diagnostic()?;
+
+        Ok(failures)
+    }
+
+    fn loudness(
+        &self,
+        video: VideoId,
+        timestamp: Option<&Timestamp>,
+        file_hash: &str,
+    ) -> Result<Option<LoudnessMeasure>> {
+        let conn = self.conn.lock().unwrap();
+        let (t_start, t_end) = bounds_millis(timestamp);
+
+        conn.query_row(
+            "SELECT integrated, range, true_peak, threshold FROM loudness
+            WHERE video_id = ? AND t_start = ? AND t_end IS ? AND file_hash = ?",
+            params![video, t_start, t_end, file_hash],
+            |row| {
+                Ok(LoudnessMeasure {
+                    integrated: row.get(0)?,
+                    range: row.get(1)?,
+                    true_peak: row.get(2)?,
+                    threshold: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+        .into_diagnostic()
+        .wrap_err("Could not query the loudness measure")
+    }
+
+    fn set_loudness(
+        &self,
+        video: VideoId,
+        timestamp: Option<&Timestamp>,
+        file_hash: &str,
+        measure: &LoudnessMeasure,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let (t_start, t_end) = bounds_millis(timestamp);
+
+        debug!("Saving the loudness of video {video} ({t_start} - {t_end:?})");
+        let tx = conn.transaction().into_diagnostic()?;
+
+        // Delete the previous measure, and the ones of previous downloads of the stream
+        tx.execute(
+            "DELETE FROM loudness
+            WHERE video_id = ? AND ((t_start = ? AND t_end IS ?) OR file_hash != ?)",
+            params![video, t_start, t_end, file_hash],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not delete the previous loudness measures")?;
+
+        tx.execute(
+            "INSERT INTO loudness
+            (video_id, t_start, t_end, file_hash, integrated, range, true_peak, threshold)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                video,
+                t_start,
+                t_end,
+                file_hash,
+                measure.integrated,
+                measure.range,
+                measure.true_peak,
+                measure.threshold
+            ],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not insert the loudness measure")?;
+
+        tx.commit().into_diagnostic()
+    }
+
+    fn override_hash(&self, video: VideoId) -> Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT override_hash FROM videos WHERE id = ?",
+            [video],
+            |row| row.get(0),
+        )
+        .into_diagnostic()
+        .wrap_err("Could not query the override hash")
+    }
+
+    fn reset_video(&self, video: VideoId, override_hash: Option<&str>) -> Result<Vec<PathBuf>> {
+        let mut conn = self.conn.lock().unwrap();
+
+        debug!("Resetting video {video}");
+        let tx = conn.transaction().into_diagnostic()?;
+
+        let clips = tx
+            .prepare("SELECT path FROM clips WHERE video_id = ?")
+            .into_diagnostic()?
+            .query_map([video], |row| row.get::<_, String>(0))
+            .into_diagnostic()
+            .wrap_err("Could not query the created clips")?
+            .map(|path| path.map(PathBuf::from))
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .into_diagnostic()?;
+
+        for table in ["work", "clips", "clip_bounds", "timestamps_comments"] {
+            tx.execute(&format!("DELETE FROM {table} WHERE video_id = ?"), [video])
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not delete the {table} rows"))?;
+        }
+
+        tx.execute(
+            "UPDATE videos
+            SET status = ?, work_len = NULL, override_hash = ?
+            WHERE id = ?",
+            params![
+                SqliteProcessedState(ProcessedState::NotProcessed),
+                override_hash,
+                video
+            ],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not reset the video")?;
+
+        tx.commit().into_diagnostic()?;
+        Ok(clips)
+    }
+
+    fn set_clip_bounds(
+        &self,
+        video: VideoId,
+        clip_idx: ClipIdx,
+        original: &Timestamp,
+        adjusted: &Timestamp,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let (original_start, original_end) = bounds_millis(Some(original));
+        let (t_start, t_end) = bounds_millis(Some(adjusted));
+
+        debug!("Saving the bounds of clip {clip_idx} of video {video} ({t_start} - {t_end:?})");
+        conn.execute(
+            "INSERT OR REPLACE INTO clip_bounds
+            (video_id, clip_idx, original_start, original_end, t_start, t_end)
+            VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                video,
+                clip_idx,
+                original_start,
+                original_end,
+                t_start,
+                t_end
+            ],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not record the clip bounds")?;
+        Ok(())
+    }
+
+    fn set_timestamps_comment(&self, video: VideoId, comment: &Comment) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        debug!("Saving the comment {} used for video {video}", comment.id);
+        conn.execute(
+            "INSERT OR REPLACE INTO timestamps_comments
+            (video_id, comment_id, author, pinned, like_count, text)
+            VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                video,
+                comment.id,
+                comment.author,
+                comment.is_pinned.unwrap_or(false),
+                comment.like_count,
+                comment.text
+            ],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not record the timestamps comment")?;
+        Ok(())
+    }
+
+    fn stored_stream(&self, video_id: &str) -> Result<Option<StoredStream>> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.query_row(
+            "SELECT path, size, hash, last_used FROM streams WHERE video_id = ?",
+            [video_id],
+            |row| {
+                Ok(StoredStream {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    size: row.get(1)?,
+                    hash: row.get(2)?,
+                    last_used: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+        .into_diagnostic()
+        .wrap_err("Could not query the stored stream")
+    }
+
+    fn stored_streams(&self) -> Result<Vec<(String, StoredStream)>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT video_id, path, size, hash, last_used FROM streams
+                ORDER BY last_used",
+            )
+            .into_diagnostic()?;
+
+        let streams = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    StoredStream {
+                        path: PathBuf::from(row.get::<_, String>(1)?),
+                        size: row.get(2)?,
+                        hash: row.get(3)?,
+                        last_used: row.get(4)?,
+                    },
+                ))
+            })
+            .into_diagnostic()
+            .wrap_err("Could not query the stored streams")?
+            .collect::<rusqlite::Result<_>>()
+            .into_diagnostic()?;
+
+        Ok(streams)
+    }
+
+    fn set_stored_stream(&self, video_id: &str, stream: &StoredStream) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        debug!("Saving the stored stream of video {video_id}");
+        conn.execute(
+            "INSERT OR REPLACE INTO streams
+            (video_id, path, size, hash, last_used)
+            VALUES (?, ?, ?, ?, ?)",
+            params![
+                video_id,
+                stream.path.to_string_lossy(),
+                stream.size,
+                stream.hash,
+                stream.last_used
+            ],
+        )
+        .into_diagnostic()
+        .wrap_err("Could not record the stored stream")?;
+        Ok(())
+    }
+
+    fn remove_stored_stream(&self, video_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        debug!("Forgetting the stored stream of video {video_id}");
+        conn.execute("DELETE FROM streams WHERE video_id = ?", [video_id])
+            .into_diagnostic()
+            .wrap_err("Could not delete the stored stream")?;
+        Ok(())
+    }
+}
+
+/// Return the timestamp bounds in milliseconds, the entire stream being `(0, None)`
+fn bounds_millis(timestamp: Option<&Timestamp>) -> (u64, Option<u64>) {
+    match timestamp {
+        Some(timestamp) => (
+            timestamp.t_start.as_millis(),
+            timestamp.t_end.map(|t_end| t_end.as_millis()),
+        ),
+        None => (0, None),
+    }
 }
 
 impl Sqlite {
     /// Create the tables if they do not already exist
     fn create_tables(&self) -> Result<()> {
-        let conn = self.conn.write().unwrap();
+        let conn = self.conn.lock().unwrap();
 
         conn.execute_batch(
             "BEGIN;
@@ -210,6 +655,31 @@ impl Sqlite {
         .into_diagnostic()?;
         Ok(())
     }
+
+    /// Apply the [`MIGRATIONS`] that have not yet been applied to the database
+    fn migrate(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .into_diagnostic()
+            .wrap_err("Could not get the database version")?;
+
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            let new_version = i + 1;
+            debug!("Migrating database to version {new_version}");
+            conn.execute_batch(&format!(
+                "BEGIN;
+                {migration}
+                PRAGMA user_version = {new_version};
+                COMMIT;"
+            ))
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not apply migration {new_version}"))?;
+        }
+
+        Ok(())
+    }
 }
 
 /// Wrapper around [`ProcessedState`] so that it can be read from/written to sqlite
@@ -223,6 +693,7 @@ impl FromSql for SqliteProcessedState {
             // for other states, we need to check other parts of the database
             0 => ProcessedState::NotProcessed,
             1 => ProcessedState::Completed,
+            2 => ProcessedState::Failed,
             n => return Err(FromSqlError::OutOfRange(n)),
         };
 
@@ -236,6 +707,7 @@ impl ToSql for SqliteProcessedState {
             // Simple cases
             ProcessedState::NotProcessed => 0,
             ProcessedState::Completed => 1,
+            ProcessedState::Failed => 2,
 
             // These are not fully completed so 0
             ProcessedState::RemainingClips(_) | ProcessedState::ProcessedClips(_) => 0,
@@ -244,3 +716,69 @@ impl ToSql for SqliteProcessedState {
         Ok(ToSqlOutput::Owned(Value::Integer(val)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_video_keeps_its_work() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Sqlite::read_or_create(&dir.path().join("cache.sqlite")).unwrap();
+
+        // Failing before any work is assigned
+        let (video, _) = cache.check_video("video").unwrap();
+        cache.set_video_as_failed(video, "download failed").unwrap();
+        assert_eq!(
+            cache.check_video("video").unwrap().1,
+            ProcessedState::Failed
+        );
+
+        // Failing again after some clips have been created
+        cache.assign_work(video, 3).unwrap();
+        cache
+            .complete_work(video, 0, Path::new("clip.ogg"), "settings")
+            .unwrap();
+        cache.set_video_as_failed(video, "download failed").unwrap();
+        assert_eq!(
+            cache.check_video("video").unwrap().1,
+            ProcessedState::RemainingClips(vec![1, 2])
+        );
+        assert_eq!(cache.failures().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn stored_stream_outlives_its_video() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Sqlite::read_or_create(&dir.path().join("cache.sqlite")).unwrap();
+
+        let (video, _) = cache.check_video("video").unwrap();
+        cache.assign_work(video, 1).unwrap();
+        cache
+            .complete_work(video, 0, Path::new("clip.ogg"), "settings")
+            .unwrap();
+        let stream = StoredStream {
+            path: PathBuf::from("video.mkv"),
+            size: 100,
+            hash: "hash".to_owned(),
+            last_used: 10,
+        };
+        cache.set_stored_stream("video", &stream).unwrap();
+
+        // Removing the video from the cache to process it again
+        {
+            let conn = cache.conn.lock().unwrap();
+            conn.execute("DELETE FROM videos WHERE id = ?", [video])
+                .unwrap();
+            let nb_clips: u32 = conn
+                .query_row("SELECT COUNT(*) FROM clips", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(nb_clips, 0);
+        }
+        assert_eq!(
+            cache.check_video("video").unwrap().1,
+            ProcessedState::NotProcessed
+        );
+        assert!(cache.stored_stream("video").unwrap().is_some());
+    }
+}
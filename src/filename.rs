@@ -0,0 +1,174 @@
+use deunicode::deunicode_with_tofu;
+
+/// The maximum length of a file name, in bytes, on most filesystems
+pub const MAX_NAME_LEN: usize = 255;
+
+/// The maximum length of the extensions given to [`file_name`], in bytes.
+///
+/// Also covers the extensions of the files created next to the output files,
+/// e.g. `.empty` placeholders or `.cue` sheets.
+pub const MAX_EXT_LEN: usize = 8;
+
+/// The names that cannot be used on Windows, whatever their extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "CONIN$", "CONOUT$", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5",
+    "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8",
+    "LPT9",
+];
+
+/// Make a file or directory name valid on the major platforms & filesystems.
+///
+/// The characters forbidden on Windows are replaced by spaces, the leading & trailing
+/// spaces and trailing dots are removed, reserved names are escaped with a `_`,
+/// and the name is truncated to [`MAX_NAME_LEN`] bytes.
+/// If `ascii` is set, the name is first transliterated to ASCII.
+pub fn sanitize(name: &str, ascii: bool) -> String {
+    sanitize_to(name, ascii, MAX_NAME_LEN)
+}
+
+/// Build a `<stem>[ (<counter>)]<dot_ext>` file name, with a sanitized stem.
+///
+/// The stem is truncated so that the file name fits in [`MAX_NAME_LEN`] bytes
+/// with any extension of up to [`MAX_EXT_LEN`] bytes, keeping the counter.
+pub fn file_name(stem: &str, counter: Option<u16>, dot_ext: &str) -> String {
+    let counter = counter.map(|n| format!(" ({n})")).unwrap_or_default();
+    let stem = sanitize_to(stem, false, MAX_NAME_LEN - MAX_EXT_LEN - counter.len());
+    format!("{stem}{counter}{dot_ext}")
+}
+
+fn sanitize_to(name: &str, ascii: bool, max_len: usize) -> String {
+    let name = if ascii {
+        deunicode_with_tofu(name, "_")
+    } else {
+        name.to_owned()
+    };
+
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let name = escape_reserved(name.trim());
+
+    // Some filesystems do not support trailing dots and spaces
+    let name = truncate(&name, max_len).trim_end_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        "_".to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Append a `_` to the names that are reserved on Windows, e.g. `con.txt` to `con_.txt`
+fn escape_reserved(name: &str) -> String {
+    let (base, rest) = name.split_at(name.find('.').unwrap_or(name.len()));
+    // Windows ignores the trailing spaces of the base name
+    let is_reserved = RESERVED_NAMES
+        .iter()
+        .any(|reserved| base.trim_end().eq_ignore_ascii_case(reserved));
+
+    if is_reserved {
+        format!("{}_{rest}", base.trim_end())
+    } else {
+        name.to_owned()
+    }
+}
+
+/// Truncate the string to at most `max_len` bytes, without splitting a character
+fn truncate(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Whether the name is valid on the major platforms & filesystems
+    fn is_valid_name(name: &str) -> bool {
+        let base = &name[..name.find('.').unwrap_or(name.len())];
+
+        !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && !name.ends_with(['.', ' '])
+            && !name.contains(['/', '\\', ':', '*', '?', '"', '<', '>', '|'])
+            && !name.chars().any(char::is_control)
+            && !RESERVED_NAMES
+                .iter()
+                .any(|reserved| base.trim_end().eq_ignore_ascii_case(reserved))
+    }
+
+    #[test]
+    fn sanitize_names() {
+        assert_eq!(sanitize("AC/DC: Live?", false), "AC DC  Live");
+        assert_eq!(sanitize(" Title... ", false), "Title");
+        assert_eq!(sanitize("..", false), "_");
+        assert_eq!(sanitize("aux", false), "aux_");
+        assert_eq!(sanitize("Con .tar.gz", false), "Con_.tar.gz");
+        assert_eq!(sanitize("Console", false), "Console");
+        assert_eq!(sanitize("Café ½ 東京", true), "Cafe 1 2 Dong Jing");
+    }
+
+    #[test]
+    fn truncate_keeps_counter() {
+        let name = file_name(&"é".repeat(200), Some(12), ".ogg");
+        assert!(name.len() <= MAX_NAME_LEN - MAX_EXT_LEN + ".ogg".len());
+        assert!(name.ends_with("é (12).ogg"));
+    }
+
+    proptest! {
+        #[test]
+        fn sanitized_names_are_valid(name in any::<String>(), ascii in any::<bool>()) {
+            let sanitized = sanitize(&name, ascii);
+            prop_assert!(is_valid_name(&sanitized), "invalid name {sanitized:?}");
+            if ascii {
+                prop_assert!(sanitized.is_ascii());
+            }
+        }
+
+        #[test]
+        fn sanitize_is_idempotent(name in any::<String>(), ascii in any::<bool>()) {
+            let sanitized = sanitize(&name, ascii);
+            prop_assert_eq!(sanitize(&sanitized, ascii), sanitized);
+        }
+
+        #[test]
+        fn sanitize_keeps_valid_names(name in "[a-zA-Z0-9_-]([a-zA-Z0-9 _-]{0,48}[a-zA-Z0-9_-])?") {
+            prop_assume!(is_valid_name(&name));
+            prop_assert_eq!(sanitize(&name, false), name);
+        }
+
+        #[test]
+        fn file_names_are_valid(
+            stem in ".{0,300}",
+            counter in proptest::option::of(2u16..),
+            ext in "\\.[a-z]{1,7}",
+        ) {
+            let name = file_name(&stem, counter, &ext);
+            prop_assert!(is_valid_name(&name), "invalid name {name:?}");
+            prop_assert!(name.ends_with(&ext));
+            if let Some(n) = counter {
+                let suffix = format!(" ({n}){ext}");
+                prop_assert!(name.ends_with(&suffix));
+            }
+        }
+
+        #[test]
+        fn file_names_are_not_truncated_when_short(stem in "[a-zA-Z0-9]{1,100}", n in 2u16..) {
+            prop_assume!(is_valid_name(&stem));
+            prop_assert_eq!(file_name(&stem, Some(n), ".ogg"), format!("{stem} ({n}).ogg"));
+        }
+    }
+}
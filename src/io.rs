@@ -1,12 +1,16 @@
 use std::{
-    fs::OpenOptions,
+    fmt::Write,
+    fs::{File, OpenOptions},
+    io::BufReader,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use miette::{miette, IntoDiagnostic, Result};
+use sha2::{Digest, Sha256};
 use tempfile::NamedTempFile;
 
-use crate::types::Extension;
+use crate::{filename::file_name, types::Extension};
 
 pub fn touch(path: &Path) -> Result<()> {
     OpenOptions::new()
@@ -34,14 +38,14 @@ pub fn find_unused_prefix(
     // Check filenames one by one until one does not exist
 
     // Format for 1st file: <title><ext>
-    output.push(format!("{title}{dot_ext}"));
+    output.push(file_name(title, None, dot_ext));
     if test_output(&output) {
         return Ok(output);
     }
 
     // Format for 2nd file and up: <title> (<count>)<ext>
     for n in 2u16.. {
-        output.set_file_name(format!("{title} ({n}){dot_ext}"));
+        output.set_file_name(file_name(title, Some(n), dot_ext));
         if test_output(&output) {
             return Ok(output);
         }
@@ -57,8 +61,48 @@ pub fn find_unused_prefix(
 /// The file destructor will be called at the handle drop.
 /// **As such, one must not simply get the file path and drop the handle.**
 pub fn named_tempfile(extension: Extension) -> Result<NamedTempFile> {
+    named_tempfile_with_suffix(extension.with_dot())
+}
+
+/// The prefix of the temporary files created in the output directory
+pub const OUTPUT_TEMPFILE_PREFIX: &str = ".gawr-";
+
+/// After how long the temporary files are considered left over by an interrupted run, and deleted
+pub const STALE_TEMPFILE_AGE: Duration = Duration::from_secs(3600);
+
+/// Create a hidden named temporary file in the given directory.
+///
+/// This allows the file to be moved to its final place in the directory
+/// with a simple rename.
+///
+/// See [`named_tempfile`] for the precautions to take with the handle.
+pub fn named_tempfile_in(dir: &Path, extension: Extension) -> Result<NamedTempFile> {
     tempfile::Builder::new()
+        .prefix(OUTPUT_TEMPFILE_PREFIX)
         .suffix(extension.with_dot())
+        .tempfile_in(dir)
+        .into_diagnostic()
+}
+
+/// Create a named temporary file whose name ends with the given suffix.
+///
+/// See [`named_tempfile`] for the precautions to take with the handle.
+pub fn named_tempfile_with_suffix(suffix: &str) -> Result<NamedTempFile> {
+    tempfile::Builder::new()
+        .suffix(suffix)
         .tempfile()
         .into_diagnostic()
 }
+
+/// Compute the SHA-256 hash of the file content, as a lowercase hexadecimal string
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut reader = BufReader::new(File::open(path).into_diagnostic()?);
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut reader, &mut hasher).into_diagnostic()?;
+
+    let mut hex = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        write!(hex, "{byte:02x}").unwrap();
+    }
+    Ok(hex)
+}
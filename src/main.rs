@@ -1,30 +1,33 @@
 mod actors;
 mod cli;
 mod database;
+mod filename;
 mod io;
 mod logging;
 mod my_regex;
 mod outside;
 mod result;
+mod stream_store;
 mod types;
 mod utils;
 
-use std::num::NonZeroUsize;
+use std::{num::NonZeroUsize, time::Duration};
 
 use actors::{
     connect_actors, Actor, ClipperActor, DownloadActor, TimestampActor, VideoId, VideoTitle,
 };
-use cli::{AppArgs, Split};
+use cli::AppArgs;
 use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
 use miette::{Context, IntoDiagnostic};
 use outside::{Ffmpeg, StreamDownloader, StreamTransformer, Ytdl};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::{
     cli::parse_cli,
-    database::{CacheDb, ProcessedState, Sqlite},
+    database::{CacheDb, Failure, ProcessedState, Sqlite},
     logging::init_logging,
     result::Result,
+    stream_store::StreamStore,
 };
 
 fn main() -> miette::Result<()> {
@@ -47,14 +50,47 @@ fn main() -> miette::Result<()> {
         .map_err(miette::Report::from)
         .wrap_err("Could not load external components")?;
     let cache = Sqlite::read_or_create(&args.cache).wrap_err("Could not load cache")?;
+
+    let stream_store = match &args.stream_store {
+        Some(dir) => {
+            // Stored in MiB and days, 0 meaning unlimited
+            let max_age = (args.stream_store_max_age > 0.0)
+                .then(|| Duration::try_from_secs_f64(args.stream_store_max_age * 24.0 * 3600.0))
+                .transpose()
+                .into_diagnostic()
+                .wrap_err("Invalid stream_store_max_age")?;
+            Some(
+                StreamStore::open(
+                    dir,
+                    (args.stream_store_max_size > 0.0)
+                        .then_some((args.stream_store_max_size * 1024.0 * 1024.0) as u64),
+                    max_age,
+                )
+                .wrap_err("Could not open the stream store")?,
+            )
+        }
+        None => None,
+    };
+    if let Some(store) = &stream_store {
+        store
+            .evict(&cache)
+            .wrap_err("Could not evict streams from the stream store")?;
+    }
+
     let nb_videos = cache
         .count_videos(None)
         .wrap_err("Could not count videos in cache")?;
     let nb_completed = cache
         .count_videos(Some(ProcessedState::Completed))
         .wrap_err("Could not count videos in cache")?;
-    let nb_pending = nb_videos - nb_completed;
-    info!("{nb_videos} videos in cache: {nb_completed} completed and {nb_pending} pending");
+    let nb_failed = cache
+        .count_videos(Some(ProcessedState::Failed))
+        .wrap_err("Could not count videos in cache")?;
+    let nb_pending = nb_videos - nb_completed - nb_failed;
+    info!(
+        "{nb_videos} videos in cache: {nb_completed} completed, \
+        {nb_failed} failed and {nb_pending} pending"
+    );
 
     // Download the playlist videos id
     info!("Get the playlist videos id");
@@ -80,11 +116,18 @@ fn main() -> miette::Result<()> {
     }
 
     std::thread::scope(|scope| -> Result<()> {
-        let (input, output) = load_actors(scope, &stream_tsf, &stream_dl, &args, &cache)?;
+        let (input, output) = load_actors(
+            scope,
+            &stream_tsf,
+            &stream_dl,
+            &args,
+            &cache,
+            stream_store.as_ref(),
+        )?;
 
         // Fill the input channel with all the tasks
-        for video_id in videos_id {
-            input.send(video_id).unwrap();
+        for video_id in &videos_id {
+            input.send(video_id.clone()).unwrap();
         }
 
         // Drop the input to indicate the end of the input data
@@ -99,6 +142,51 @@ fn main() -> miette::Result<()> {
     })?;
 
     info!("All tasks completed");
+
+    // The streams of this run may have made the store too large
+    if let Some(store) = &stream_store {
+        store
+            .evict(&cache)
+            .wrap_err("Could not evict streams from the stream store")?;
+    }
+
+    print_failures_summary(&cache, &videos_id)?;
+    Ok(())
+}
+
+/// Log the videos and clips of this run that have failed, and why
+fn print_failures_summary(cache: &Sqlite, videos_id: &[String]) -> Result<()> {
+    let failures: Vec<_> = cache
+        .failures()
+        .wrap_err("Could not get the failures from the cache")?
+        .into_iter()
+        .filter(|failure| videos_id.contains(&failure.video_id))
+        .collect();
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    warn!(
+        "{} failures, they will be retried on the next run:",
+        failures.len()
+    );
+    for Failure {
+        video_id,
+        clip_idx,
+        error,
+        attempts,
+    } in failures
+    {
+        match clip_idx {
+            Some(clip_idx) => warn!(
+                "- video {video_id}, clip {} ({attempts} attempts): {error}",
+                clip_idx + 1
+            ),
+            None => warn!("- video {video_id} ({attempts} attempts): {error}"),
+        }
+    }
+
     Ok(())
 }
 
@@ -124,6 +212,7 @@ fn load_actors<'a>(
     stream_dl: &'a dyn StreamDownloader,
     args: &'a AppArgs,
     cache: &'a Sqlite,
+    stream_store: Option<&'a StreamStore>,
 ) -> Result<(Sender<VideoId>, Receiver<VideoTitle>)> {
     let nb_cores = NonZeroUsize::new(args.cores)
         .unwrap_or_else(|| std::thread::available_parallelism().unwrap());
@@ -132,21 +221,12 @@ fn load_actors<'a>(
     // the rest of the program to run
     let clipper_threads = usize::max(1, nb_cores.get() - 1);
 
-    let skip_timestamps = matches!(args.split, Split::Full);
-
     // Initialize the actors
-    let mut dl_actor = DownloadActor::new(stream_dl, skip_timestamps, &args.clip_regex, cache);
-    let mut tstamp_actor = TimestampActor::new(cache);
+    let mut dl_actor = DownloadActor::new(stream_dl, stream_tsf, args, cache, stream_store);
+    let mut tstamp_actor = TimestampActor::new(stream_tsf, args.snap_window, cache);
     let mut clip_actors = Vec::with_capacity(clipper_threads);
     for id in 0..clipper_threads {
-        clip_actors.push(ClipperActor::new(
-            id,
-            stream_tsf,
-            &args.out,
-            args.ext,
-            cache,
-            args.bitrate,
-        ));
+        clip_actors.push(ClipperActor::new(id, stream_tsf, args, cache));
     }
 
     // Connect the actors together
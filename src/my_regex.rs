@@ -18,22 +18,60 @@ macro_rules! title {
         r#"(?P<title>.+)"#
     };
 }
+/// The clip title, as short as possible so that it does not include the separator
+macro_rules! lazy_title {
+    () => {
+        r#"(?P<title>.+?)"#
+    };
+}
+/// A timestamp value, either "1:02:03(.456)" or "1h02m03(.456)s" formatted.
+/// At least two units are needed, so that e.g. "the 90s" is not read as a timestamp
+macro_rules! tstamp_value {
+    () => {
+        concat!(
+            r#"(?:[0-9]+(?::[0-9]+)+(?:\.[0-9]+)?"#,
+            r#"|[0-9]+h[0-9]+m(?:[0-9]+(?:\.[0-9]+)?s)?"#,
+            r#"|[0-9]+h[0-9]+(?:\.[0-9]+)?s"#,
+            r#"|[0-9]+m[0-9]+(?:\.[0-9]+)?s)"#
+        )
+    };
+}
+/// An optional opening bracket before a timestamp, e.g. "[0:00] Title"
+macro_rules! opt_open {
+    () => {
+        r#"[\[(]?"#
+    };
+}
 /// The clip timestamp
 macro_rules! tstamp_start {
     () => {
-        r#"(?P<time>[0-9]+(:[0-9]+)+)"#
+        concat!(r#"(?P<time>"#, tstamp_value!(), r#")"#)
     };
 }
-/// An optional separator
+/// An optional separator, any symbol (e.g. `-`, `.`, `]`) followed by at least one space.
+/// Letters & digits are left out, so that they are not taken from the titles
 macro_rules! opt_sep {
     () => {
-        r#" *.? +"#
+        r#"(?: *[^\w\s])? +"#
+    };
+}
+/// An optional separator after a title, like [`opt_sep`] except for the symbols
+/// usually ending titles (e.g. `)`, `!`), so that they are kept in the titles
+macro_rules! opt_title_sep {
+    () => {
+        r#"(?: *[^\w\s)\]}!?'"])? +"#
     };
 }
 /// An optional second timestamp, indicating the end of the clip
 macro_rules! opt_tstamp_end {
     () => {
-        concat!(opt_sep!(), r#"(?:[0-9]+(:[0-9]+)+)?"#)
+        concat!(
+            r#"(?:"#,
+            opt_sep!(),
+            r#"(?P<end>"#,
+            tstamp_value!(),
+            r#"))?"#
+        )
     };
 }
 macro_rules! timestamp {
@@ -43,14 +81,87 @@ macro_rules! timestamp {
 }
 /// Pattern 1: An optional index, the timestamp, an optional separator, the title
 /// Example: "6:66 Music That Will Make You Go Insane !!!"
-const PATTERN1: &str = concat!("^", opt_idx!(), timestamp!(), opt_sep!(), title!(), "$");
+const PATTERN1: &str = concat!(
+    "^",
+    opt_idx!(),
+    opt_open!(),
+    timestamp!(),
+    opt_sep!(),
+    title!(),
+    "$"
+);
 
 /// Pattern 2: An optional index, the title, an optional separator, the timestamp
 /// Example: "5. My Very Cool Title - 05:49"
-const PATTERN2: &str = concat!("^", opt_idx!(), title!(), opt_sep!(), timestamp!(), "$");
+const PATTERN2: &str = concat!(
+    "^",
+    opt_idx!(),
+    lazy_title!(),
+    opt_title_sep!(),
+    timestamp!(),
+    "$"
+);
 
 static DEFAULT_RE_LIST: OnceLock<[Regex; 2]> = OnceLock::new();
 
 pub fn get_default_re_list() -> &'static [Regex] {
     DEFAULT_RE_LIST.get_or_init(|| [Regex::new(PATTERN1).unwrap(), Regex::new(PATTERN2).unwrap()])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<(&str, Option<&str>, &str)> {
+        let cap = get_default_re_list()
+            .iter()
+            .find_map(|re| re.captures(line))?;
+        Some((
+            cap.name("time")?.as_str(),
+            cap.name("end").map(|m| m.as_str()),
+            cap.name("title")?.as_str(),
+        ))
+    }
+
+    #[test]
+    fn default_patterns() {
+        assert_eq!(parse("0:00 Intro"), Some(("0:00", None, "Intro")));
+        assert_eq!(parse("00:00 - Intro"), Some(("00:00", None, "Intro")));
+        assert_eq!(parse("1:02:03 | Outro"), Some(("1:02:03", None, "Outro")));
+        assert_eq!(
+            parse("00:00 - 03:12 First Song"),
+            Some(("00:00", Some("03:12"), "First Song"))
+        );
+        assert_eq!(parse("1h02m03s Title"), Some(("1h02m03s", None, "Title")));
+        assert_eq!(
+            parse("5. My Very Cool Title - 05:49"),
+            Some(("05:49", None, "My Very Cool Title"))
+        );
+        assert_eq!(parse("Title 05:49"), Some(("05:49", None, "Title")));
+        assert_eq!(
+            parse("Title 05:49 - 08:12"),
+            Some(("05:49", Some("08:12"), "Title"))
+        );
+        assert_eq!(parse("Title 1m30s"), Some(("1m30s", None, "Title")));
+        assert_eq!(parse("Just some text"), None);
+        assert_eq!(parse("Best of the 90s"), None);
+        assert_eq!(parse("Remastered in 4k 2m"), None);
+        assert_eq!(parse("3h of relaxing music"), None);
+        assert_eq!(parse("45s Intro"), None);
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(parse("00:00. Title"), Some(("00:00", None, "Title")));
+        assert_eq!(parse("1:23) Title"), Some(("1:23", None, "Title")));
+        assert_eq!(parse("[0:00] Title"), Some(("0:00", None, "Title")));
+        assert_eq!(parse("(1:02:03) Title"), Some(("1:02:03", None, "Title")));
+        assert_eq!(parse("0:00 ~ Title"), Some(("0:00", None, "Title")));
+        assert_eq!(parse("Title: 05:49"), Some(("05:49", None, "Title")));
+        assert_eq!(
+            parse("Song (Remix) 05:49"),
+            Some(("05:49", None, "Song (Remix)"))
+        );
+        assert_eq!(parse("Hello! 05:49"), Some(("05:49", None, "Hello!")));
+    }
+}
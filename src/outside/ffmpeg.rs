@@ -1,28 +1,163 @@
-use std::{ffi::OsStr, fmt::Debug, path::Path};
+use std::{ffi::OsStr, fmt::Debug, io::Write, path::Path, process::Command};
 
+use base64::{prelude::BASE64_STANDARD, Engine};
 use miette::{miette, Context, IntoDiagnostic, Result};
 
-use crate::types::{Bitrate, Timestamp};
+use crate::{
+    io::named_tempfile_with_suffix,
+    types::{
+        Bitrate, Codec, Encoding, Extension, LoudnessMeasure, LoudnessTarget, Tags, TimeOffset,
+        Timestamp,
+    },
+};
 
 use super::command::{assert_success_command, run_command, Capture, FFMPEG, FFXXX_DEFAULT_ARGS};
 
+/// A transformation applied to the audio while encoding it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioFilter {
+    /// Normalize the loudness to the target, from the loudness measured on the clip
+    Loudnorm {
+        target: LoudnessTarget,
+        measured: LoudnessMeasure,
+    },
+    /// Apply the same gain, in dB, to the entire clip
+    Gain(f64),
+    /// Remove the silence at the start and the end of the clip.
+    ///
+    /// The silence is the audio below the `threshold`, in dB.
+    /// At the start, sounds shorter than `min_duration`, in seconds, are considered part of the silence.
+    /// At the end, everything after a silence of at least `end_gap` seconds is removed.
+    TrimSilence {
+        threshold: f64,
+        min_duration: f64,
+        end_gap: f64,
+    },
+    /// Fade in the start of the clip during the given number of seconds
+    FadeIn(f64),
+    /// Fade out the clip from `start` during `duration`, both in seconds
+    FadeOut { start: f64, duration: f64 },
+}
+
+impl AudioFilter {
+    /// Return the `ffmpeg` filter graph description of the filter
+    fn to_ffmpeg(self) -> String {
+        match self {
+            AudioFilter::Loudnorm { target, measured } => format!(
+                "loudnorm={target}:linear=true:\
+                measured_I={}:\
+                measured_LRA={}:\
+                measured_tp={}:\
+                measured_thresh={}",
+                measured.integrated, measured.range, measured.true_peak, measured.threshold
+            ),
+            AudioFilter::Gain(gain) => format!("volume={gain}dB"),
+            // Not reversing the audio to trim its end, as it would hold the entire clip in memory
+            AudioFilter::TrimSilence {
+                threshold,
+                min_duration,
+                end_gap,
+            } => format!(
+                "silenceremove=start_periods=1:\
+                start_threshold={threshold}dB:\
+                start_duration={min_duration}:\
+                stop_periods=1:\
+                stop_threshold={threshold}dB:\
+                stop_duration={end_gap}"
+            ),
+            AudioFilter::FadeIn(duration) => format!("afade=t=in:d={duration}"),
+            AudioFilter::FadeOut { start, duration } => {
+                format!("afade=t=out:st={start}:d={duration}")
+            }
+        }
+    }
+}
+
 pub trait StreamTransformer: Sync + Debug {
-    /// Extract a clip containing the stream data between the two
-    /// timestamps from the input file to the output file.
+    /// Measure the EBU R128 loudness of an audio stream,
+    /// only between the timestamp bounds if one is given.
+    fn measure_loudness(
+        &self,
+        input: &Path,
+        timestamp: Option<&Timestamp>,
+    ) -> Result<LoudnessMeasure>;
+
+    /// Create a clip containing the stream data between the timestamp bounds
+    /// from the input file to the output file, in a single pass.
+    ///
+    /// The audio is transformed by the filters, in order, then encoded as given,
+    /// in which case the clip bounds are sample-accurate. When the audio is copied, they are aligned on the
+    /// packets of the input stream instead, so it must only be done for the entire stream.
     ///
-    /// If the end timestamp is not specified, the clip should
+    /// The input metadata is dropped and replaced by the given tags.
+    ///
+    /// If the end of the timestamp is not specified, the clip should
     /// continue until the end of the stream.
-    fn extract_clip(
+    fn create_clip(
+        &self,
+        input: &Path,
+        output: &Path,
+        timestamp: &Timestamp,
+        filters: &[AudioFilter],
+        encoding: Encoding,
+        tags: &Tags,
+    ) -> Result<()>;
+
+    /// Find the quietest point of the stream between the `start` and `end` offsets.
+    ///
+    /// When several consecutive points are equally quiet, e.g. in a silence,
+    /// the middle of the longest run of them is returned.
+    fn quietest_point(
+        &self,
+        input: &Path,
+        start: TimeOffset,
+        end: TimeOffset,
+    ) -> Result<TimeOffset>;
+
+    /// Find the silences of the stream, i.e. the audio below the `threshold`, in dB,
+    /// during at least `min_duration` seconds.
+    ///
+    /// The silences are returned in order, as their start and end offsets.
+    /// The end is `None` if the silence lasts until the end of the stream.
+    fn detect_silences(
+        &self,
+        input: &Path,
+        threshold: f64,
+        min_duration: f64,
+    ) -> Result<Vec<(TimeOffset, Option<TimeOffset>)>>;
+
+    /// Return the name of the codec of the first audio stream of the file, e.g. `opus`
+    fn audio_codec(&self, input: &Path) -> Result<String>;
+
+    /// Copy the input file to the output file, embedding the timestamps as chapters.
+    ///
+    /// The chapters without an end last until the start of the next one,
+    /// or until the `duration` of the stream for the last one.
+    ///
+    /// The `tags` of the input file are given as some containers need them to be rewritten.
+    fn embed_chapters(
         &self,
         input: &Path,
         output: &Path,
-        start: &Timestamp,
-        end: Option<&Timestamp>,
-        album: &str, // TODO: This is weird, refactor to have better API
+        chapters: &[Timestamp],
+        duration: TimeOffset,
+        tags: &Tags,
     ) -> Result<()>;
 
-    /// Normalize an audio stream
-    fn normalize_audio(&self, input: &Path, output: &Path, bitrate: Bitrate) -> Result<()>;
+    /// Copy the input file to the output file, embedding the `cover` image as cover art.
+    ///
+    /// If `crop_square` is set, the image is cropped to a centered square.
+    /// The container of the output file must support cover art.
+    ///
+    /// The `tags` of the input file are given as some containers need them to be rewritten.
+    fn embed_cover_art(
+        &self,
+        input: &Path,
+        output: &Path,
+        cover: &Path,
+        crop_square: bool,
+        tags: &Tags,
+    ) -> Result<()>;
 }
 
 /// Interface for the [ffprobe](https://ffmpeg.org) program
@@ -40,42 +175,21 @@ impl Ffmpeg {
 }
 
 impl StreamTransformer for Ffmpeg {
-    fn extract_clip(
+    fn measure_loudness(
         &self,
         input: &Path,
-        output: &Path,
-        start: &Timestamp,
-        end: Option<&Timestamp>,
-        album: &str,
-    ) -> Result<()> {
-        assert_success_command(FFMPEG, |cmd| {
-            let mut cmd = cmd
-                .args(FFXXX_DEFAULT_ARGS)
-                .arg("-y")
-                .args([OsStr::new("-i"), input.as_os_str()])
-                .args(["-map_metadata", "-1"])
-                .args(["-metadata", &format!("album={album}")])
-                .args(["-ss", &start.t_start]);
-
-            if let Some(end) = end {
-                cmd = cmd.args(["-to", &end.t_start]);
-            }
-
-            cmd.args(["-c:a", "copy"]).arg("--").arg(output)
-        })
-    }
-
-    fn normalize_audio(&self, input: &Path, output: &Path, bitrate: Bitrate) -> Result<()> {
-        // First pass to generate the statistics
-        let input = input.as_os_str();
+        timestamp: Option<&Timestamp>,
+    ) -> Result<LoudnessMeasure> {
         let res = run_command(
             FFMPEG,
             |cmd| {
                 // Do not use FFXXX_DEFAULT_ARGS as it would remove the wanted output
-                cmd.arg("-hide_banner")
-                    .arg("-y")
-                    .args([OsStr::new("-i"), input])
-                    .args(["-pass", "1"])
+                let cmd = cmd.arg("-hide_banner").arg("-y");
+                let cmd = match timestamp {
+                    Some(timestamp) => input_args(cmd, input, timestamp),
+                    None => cmd.args([OsStr::new("-i"), input.as_os_str()]),
+                };
+                cmd.args(["-pass", "1"])
                     .args(["-filter:a", "loudnorm=print_format=json"])
                     .args(["-f", "null", "-"])
             },
@@ -106,36 +220,666 @@ impl StreamTransformer for Ffmpeg {
             .as_object()
             .ok_or_else(|| miette!("JSON output is not an object"))?;
 
-        let get_str = |k: &str| -> Result<&str> {
+        let get_f64 = |k: &str| -> Result<f64> {
             json.get(k)
                 .ok_or_else(|| miette!(format!("Key {k} not found in JSON object")))?
                 .as_str()
-                .ok_or_else(|| miette!(format!("Value of key {k} is not a string")))
+                .ok_or_else(|| miette!(format!("Value of key {k} is not a string")))?
+                .parse()
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Value of key {k} is not a number"))
         };
 
-        let input_i = get_str("input_i")?;
-        let input_lra = get_str("input_lra")?;
-        let input_tp = get_str("input_tp")?;
-        let input_thresh = get_str("input_thresh")?;
-
-        // Second pass to apply the normalization using the previous statistics
-        let filter = format!(
-            "loudnorm=linear=true:\
-            measured_I={input_i}:\
-            measured_LRA={input_lra}:\
-            measured_tp={input_tp}:\
-            measured_thresh={input_thresh}"
-        );
+        Ok(LoudnessMeasure {
+            integrated: get_f64("input_i")?,
+            range: get_f64("input_lra")?,
+            true_peak: get_f64("input_tp")?,
+            threshold: get_f64("input_thresh")?,
+        })
+    }
+
+    fn create_clip(
+        &self,
+        input: &Path,
+        output: &Path,
+        timestamp: &Timestamp,
+        filters: &[AudioFilter],
+        encoding: Encoding,
+        tags: &Tags,
+    ) -> Result<()> {
+        let filter = filters
+            .iter()
+            .map(|filter| filter.to_ffmpeg())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        assert_success_command(FFMPEG, |cmd| {
+            let cmd = input_args(cmd.args(FFXXX_DEFAULT_ARGS).arg("-y"), input, timestamp).args([
+                "-map",
+                "0:a",
+                "-map_metadata",
+                "-1",
+            ]);
+            let cmd = if filter.is_empty() {
+                cmd
+            } else {
+                cmd.args(["-filter:a", &filter])
+            };
+            let cmd = match encoding {
+                Encoding::Copy => cmd.args(["-c:a", "copy"]),
+                Encoding::Codec(codec, bitrate) => encoder_args(cmd, codec, bitrate),
+            };
+            metadata_args(cmd, output, tags).arg("--").arg(output)
+        })
+    }
+
+    fn quietest_point(
+        &self,
+        input: &Path,
+        start: TimeOffset,
+        end: TimeOffset,
+    ) -> Result<TimeOffset> {
+        let bounds = Timestamp {
+            t_start: start,
+            t_end: Some(end),
+            title: String::new(),
+            artist: None,
+        };
+        let res = run_command(
+            FFMPEG,
+            |cmd| {
+                // Do not use FFXXX_DEFAULT_ARGS as it would remove the wanted output
+                input_args(cmd.arg("-hide_banner"), input, &bounds)
+                    .args(["-map", "0:a"])
+                    // Print the loudness of every decoded frame
+                    .args([
+                        "-filter:a",
+                        "astats=metadata=1:reset=1,\
+                        ametadata=mode=print:key=lavfi.astats.Overall.RMS_level",
+                    ])
+                    .args(["-f", "null", "-"])
+            },
+            Capture::STDERR,
+        )?;
+
+        let stderr = String::from_utf8_lossy(&res.stderr);
+        let offset = quietest_frame(&stderr)
+            .ok_or_else(|| miette!("Could not parse the loudness of the audio frames"))?;
+        TimeOffset::from_secs_f64(start.as_secs_f64() + offset.max(0.0))
+            .into_diagnostic()
+            .wrap_err("Invalid offset of the quietest audio frame")
+    }
+
+    fn detect_silences(
+        &self,
+        input: &Path,
+        threshold: f64,
+        min_duration: f64,
+    ) -> Result<Vec<(TimeOffset, Option<TimeOffset>)>> {
+        let res = run_command(
+            FFMPEG,
+            |cmd| {
+                // Do not use FFXXX_DEFAULT_ARGS as it would remove the wanted output
+                cmd.arg("-hide_banner")
+                    .args([OsStr::new("-i"), input.as_os_str()])
+                    .args(["-map", "0:a"])
+                    .args([
+                        "-filter:a",
+                        &format!("silencedetect=noise={threshold}dB:duration={min_duration}"),
+                    ])
+                    .args(["-f", "null", "-"])
+            },
+            Capture::STDERR,
+        )?;
+        if !res.status.success() {
+            return Err(miette!("Could not analyze the stream audio"));
+        }
+
+        // e.g. "[silencedetect @ 0x...] silence_start: 120.5"
+        //      "[silencedetect @ 0x...] silence_end: 123.25 | silence_duration: 2.75"
+        let parse_offset = |rest: &str| {
+            let secs: f64 = rest.split_whitespace().next()?.parse().ok()?;
+            TimeOffset::from_secs_f64(secs.max(0.0)).ok()
+        };
+
+        let stderr = String::from_utf8_lossy(&res.stderr);
+        let mut silences = vec![];
+        let mut silence_start = None;
+        for line in stderr.lines() {
+            if let Some((_, rest)) = line.split_once("silence_start: ") {
+                silence_start = parse_offset(rest);
+            } else if let Some((_, rest)) = line.split_once("silence_end: ") {
+                if let (Some(start), Some(end)) = (silence_start.take(), parse_offset(rest)) {
+                    silences.push((start, Some(end)));
+                }
+            }
+        }
+        // The last silence may not end before the end of the stream
+        if let Some(start) = silence_start {
+            silences.push((start, None));
+        }
+
+        Ok(silences)
+    }
+
+    fn audio_codec(&self, input: &Path) -> Result<String> {
+        // Without any output file, ffmpeg prints the input streams then fails
+        let res = run_command(
+            FFMPEG,
+            |cmd| {
+                cmd.arg("-hide_banner")
+                    .args([OsStr::new("-i"), input.as_os_str()])
+            },
+            Capture::STDERR,
+        )?;
+
+        // e.g. "  Stream #0:0: Audio: opus, 48000 Hz, stereo, fltp"
+        let stderr = String::from_utf8_lossy(&res.stderr);
+        stderr
+            .lines()
+            .filter(|line| line.trim_start().starts_with("Stream #"))
+            .find_map(|line| line.split_once("Audio: "))
+            .and_then(|(_, rest)| rest.split([',', ' ']).next())
+            .map(str::to_owned)
+            .ok_or_else(|| miette!("No audio stream found in the file"))
+    }
+
+    fn embed_chapters(
+        &self,
+        input: &Path,
+        output: &Path,
+        chapters: &[Timestamp],
+        duration: TimeOffset,
+        tags: &Tags,
+    ) -> Result<()> {
+        let mut content = String::from(";FFMETADATA1\n");
+        for (i, chapter) in chapters.iter().enumerate() {
+            let t_end = chapter
+                .t_end
+                .or_else(|| chapters.get(i + 1).map(|next| next.t_start))
+                .unwrap_or(duration)
+                .max(chapter.t_start);
+            content.push_str(&format!(
+                "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+                chapter.t_start.as_millis(),
+                t_end.as_millis(),
+                escape_ffmetadata(&chapter.title)
+            ));
+        }
+
+        let mut metadata = named_tempfile_with_suffix(".txt")?;
+        metadata
+            .write_all(content.as_bytes())
+            .into_diagnostic()
+            .wrap_err("Could not write the chapters file")?;
 
-        let output = output.as_os_str();
+        assert_success_command(FFMPEG, |cmd| {
+            let cmd = cmd
+                .args(FFXXX_DEFAULT_ARGS)
+                .arg("-y")
+                .args([OsStr::new("-i"), input.as_os_str()])
+                .args(["-f", "ffmetadata"])
+                .args([OsStr::new("-i"), metadata.path().as_os_str()])
+                .args(["-map", "0", "-map_chapters", "1", "-c", "copy"]);
+            metadata_args(cmd, output, tags).arg("--").arg(output)
+        })
+    }
+
+    fn embed_cover_art(
+        &self,
+        input: &Path,
+        output: &Path,
+        cover: &Path,
+        crop_square: bool,
+        tags: &Tags,
+    ) -> Result<()> {
+        // Convert the image to JPEG, which is supported by every container & player
+        let jpeg = named_tempfile_with_suffix(".jpg")?;
+        assert_success_command(FFMPEG, |cmd| {
+            let cmd = cmd
+                .args(FFXXX_DEFAULT_ARGS)
+                .arg("-y")
+                .args([OsStr::new("-i"), cover.as_os_str()]);
+            let cmd = if crop_square {
+                cmd.args(["-vf", "crop='min(iw,ih)':'min(iw,ih)'"])
+            } else {
+                cmd
+            };
+            cmd.args(["-frames:v", "1"]).arg(jpeg.path())
+        })
+        .wrap_err("Could not convert the cover art to JPEG")?;
+
+        match Extension::from_path(output) {
+            Some(Extension::Ogg | Extension::Opus) => {
+                embed_picture_block(input, output, jpeg.path(), tags)
+            }
+            Some(Extension::Flac | Extension::M4a | Extension::Mp3) => {
+                assert_success_command(FFMPEG, |cmd| {
+                    let cmd = cmd
+                        .args(FFXXX_DEFAULT_ARGS)
+                        .arg("-y")
+                        .args([OsStr::new("-i"), input.as_os_str()])
+                        .args([OsStr::new("-i"), jpeg.path().as_os_str()])
+                        .args(["-map", "0:a", "-map", "1:v", "-c", "copy"])
+                        .args(["-disposition:v:0", "attached_pic"]);
+                    // Write the tags again, as the mp4 muxer needs to be told to keep them
+                    metadata_args(cmd, output, tags).arg(output)
+                })
+            }
+            Some(Extension::Mka | Extension::Mkv) => assert_success_command(FFMPEG, |cmd| {
+                cmd.args(FFXXX_DEFAULT_ARGS)
+                    .arg("-y")
+                    .args([OsStr::new("-i"), input.as_os_str()])
+                    .args(["-map", "0", "-c", "copy"])
+                    .args([OsStr::new("-attach"), jpeg.path().as_os_str()])
+                    .args(["-metadata:s:t", "mimetype=image/jpeg"])
+                    .args(["-metadata:s:t", "filename=cover.jpg"])
+                    .arg(output)
+            }),
+            Some(Extension::Webm) => Err(miette!("The webm container does not support cover art")),
+            None => Err(miette!("Invalid output extension")),
+        }
+    }
+}
+
+/// Add the arguments to read the input file between the timestamp bounds.
+///
+/// The input is seeked directly to the start of the clip, and the decoding
+/// of the stream is trimmed to the exact samples of the clip bounds.
+fn input_args<'c>(cmd: &'c mut Command, input: &Path, timestamp: &Timestamp) -> &'c mut Command {
+    let cmd = cmd
+        .args(["-accurate_seek", "-ss", &timestamp.t_start.to_string()])
+        .args([OsStr::new("-i"), input.as_os_str()]);
+    match timestamp.t_end {
+        // After an input seek, the output timestamps start at 0
+        Some(t_end) => cmd.args(["-t", &t_end.saturating_sub(timestamp.t_start).to_string()]),
+        None => cmd,
+    }
+}
+
+/// Parse the output of the `ametadata` filter printing the RMS level of the frames,
+/// and return the time of the middle of the longest run of the quietest frames.
+///
+/// Frames within 1 dB of the quietest one are considered as quiet as it.
+fn quietest_frame(stderr: &str) -> Option<f64> {
+    // e.g. "[Parsed_ametadata_1 @ 0x...] frame:12   pts:11520   pts_time:0.24"
+    //      "[Parsed_ametadata_1 @ 0x...] lavfi.astats.Overall.RMS_level=-inf"
+    let mut frames = vec![];
+    let mut pts_time = None;
+    for line in stderr.lines() {
+        if let Some((_, rest)) = line.split_once("pts_time:") {
+            pts_time = rest.split_whitespace().next().and_then(|t| t.parse().ok());
+        } else if let Some((_, level)) = line.split_once("lavfi.astats.Overall.RMS_level=") {
+            if let (Some(time), Ok(level)) = (pts_time.take(), level.trim().parse::<f64>()) {
+                frames.push((time, level));
+            }
+        }
+    }
+
+    let quietest = frames
+        .iter()
+        .map(|&(_, level)| level)
+        .min_by(f64::total_cmp)?;
+
+    // Find the longest run of frames as quiet as the quietest one
+    let mut best: Option<(f64, f64)> = None;
+    let mut run_start = None;
+    for (i, &(time, level)) in frames.iter().enumerate() {
+        if level <= quietest + 1.0 {
+            let start = *run_start.get_or_insert(time);
+            let is_run_end = frames
+                .get(i + 1)
+                .is_none_or(|&(_, next)| next > quietest + 1.0);
+            if is_run_end && best.is_none_or(|(s, e)| time - start > e - s) {
+                best = Some((start, time));
+            }
+        } else {
+            run_start = None;
+        }
+    }
+
+    best.map(|(start, end)| (start + end) / 2.0)
+}
+
+/// The sample rate of the encoded audio, supported by every codec.
+///
+/// Otherwise, the `loudnorm` filter upsamples the audio to 192 kHz in its dynamic mode,
+/// which the lossless and aac encoders would keep.
+const SAMPLE_RATE: &str = "48000";
+
+/// Add the arguments to encode the audio with the codec at the given bitrate.
+/// The bitrate is not used for lossless codecs.
+fn encoder_args(cmd: &mut Command, codec: Codec, bitrate: Bitrate) -> &mut Command {
+    let cmd = cmd
+        .args(["-c:a", codec.ffmpeg_encoder()])
+        .args(["-ar", SAMPLE_RATE]);
+    if codec.is_lossless() {
+        cmd
+    } else {
+        cmd.args(["-b:a", &bitrate.to_string()])
+    }
+}
+
+/// Add the arguments to write the tags to the output file
+fn metadata_args<'c>(cmd: &'c mut Command, output: &Path, tags: &Tags) -> &'c mut Command {
+    let mut cmd = cmd;
+    for (key, value) in tags.to_pairs() {
+        cmd = cmd.args(["-metadata", &format!("{key}={value}")]);
+    }
+
+    // The mp4 muxer drops the non-standard tags otherwise
+    if Extension::from_path(output) == Some(Extension::M4a) {
+        cmd = cmd.args(["-movflags", "use_metadata_tags"]);
+    }
+    cmd
+}
+
+/// Embed the JPEG cover art in an ogg file, through the `METADATA_BLOCK_PICTURE` comment.
+///
+/// The value is too big to be given on the command line, so the metadata are
+/// given through an [ffmetadata](https://ffmpeg.org/ffmpeg-formats.html#Metadata-1) file,
+/// which replaces all the metadata of the file.
+fn embed_picture_block(input: &Path, output: &Path, jpeg: &Path, tags: &Tags) -> Result<()> {
+    let data = std::fs::read(jpeg)
+        .into_diagnostic()
+        .wrap_err("Could not read the cover art")?;
+    let (width, height) =
+        jpeg_dimensions(&data).ok_or_else(|| miette!("Could not read the JPEG dimensions"))?;
+
+    // FLAC picture block, see https://xiph.org/flac/format.html#metadata_block_picture
+    const MIME: &[u8] = b"image/jpeg";
+    let mut block = Vec::with_capacity(32 + MIME.len() + data.len());
+    block.extend(3u32.to_be_bytes()); // Picture type: front cover
+    block.extend((MIME.len() as u32).to_be_bytes());
+    block.extend(MIME);
+    block.extend(0u32.to_be_bytes()); // Description length
+    block.extend(width.to_be_bytes());
+    block.extend(height.to_be_bytes());
+    block.extend(24u32.to_be_bytes()); // Color depth
+    block.extend(0u32.to_be_bytes()); // Number of colors (not indexed)
+    block.extend((data.len() as u32).to_be_bytes());
+    block.extend(&data);
+
+    // Write the tags along with the picture
+    let mut metadata = named_tempfile_with_suffix(".txt")?;
+    let mut content = String::from(";FFMETADATA1\n");
+    for (key, value) in tags.to_pairs() {
+        content.push_str(&format!("{key}={}\n", escape_ffmetadata(&value)));
+    }
+    content.push_str(&format!(
+        "METADATA_BLOCK_PICTURE={}\n",
+        escape_ffmetadata(&BASE64_STANDARD.encode(block))
+    ));
+    metadata
+        .write_all(content.as_bytes())
+        .into_diagnostic()
+        .wrap_err("Could not write the metadata file")?;
+
+    assert_success_command(FFMPEG, |cmd| {
+        cmd.args(FFXXX_DEFAULT_ARGS)
+            .arg("-y")
+            .args([OsStr::new("-i"), input.as_os_str()])
+            .args(["-f", "ffmetadata"])
+            .args([OsStr::new("-i"), metadata.path().as_os_str()])
+            .args(["-map", "0:a", "-map_metadata", "1", "-c", "copy"])
+            .arg(output)
+    })
+}
+
+/// Escape the special characters of an ffmetadata value
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Read the (width, height) of a JPEG image from its start of frame segment
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    // Skip the start of image marker
+    let mut i = 2;
+    while i + 9 < data.len() {
+        if data[i] != 0xFF {
+            return None;
+        }
+        let marker = data[i + 1];
+        let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+
+        // Start of frame markers, except DHT (C4), JPG (C8) and DAC (CC)
+        if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let height = u16::from_be_bytes([data[i + 5], data[i + 6]]);
+            let width = u16::from_be_bytes([data[i + 7], data[i + 8]]);
+            return Some((width.into(), height.into()));
+        }
+
+        i += 2 + len;
+    }
+    None
+}
+
+// The tests running ffmpeg are ignored when it is not found at build time
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{io::named_tempfile, types::TimeOffset};
+
+    /// Create a 10 seconds long sine wave FLAC file
+    fn sine_stream() -> NamedTempFile {
+        let file = named_tempfile(Extension::Flac).unwrap();
+        assert_success_command(FFMPEG, |cmd| {
+            cmd.args(FFXXX_DEFAULT_ARGS)
+                .arg("-y")
+                .args(["-f", "lavfi"])
+                .args(["-i", "sine=frequency=440:sample_rate=48000:duration=10"])
+                .args(["-c:a", "flac"])
+                .arg(file.path())
+        })
+        .unwrap();
+        file
+    }
+
+    /// Read the duration of a file, as reported by `ffmpeg`
+    fn duration(path: &Path) -> TimeOffset {
+        let res = run_command(
+            FFMPEG,
+            |cmd| cmd.arg("-hide_banner").arg("-i").arg(path),
+            Capture::STDERR,
+        )
+        .unwrap();
+
+        // e.g. "  Duration: 00:00:03.75, start: 0.000000, bitrate: 564 kb/s"
+        let stderr = String::from_utf8_lossy(&res.stderr);
+        let (_, rest) = stderr.split_once("Duration: ").unwrap();
+        rest.split(',').next().unwrap().parse().unwrap()
+    }
+
+    fn timestamp(t_start: f64, t_end: Option<f64>) -> Timestamp {
+        Timestamp {
+            t_start: TimeOffset::from_secs_f64(t_start).unwrap(),
+            t_end: t_end.map(|t_end| TimeOffset::from_secs_f64(t_end).unwrap()),
+            title: "Clip".to_owned(),
+            artist: None,
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(has_ffmpeg), ignore = "needs ffmpeg")]
+    fn clip_durations() {
+        let ffmpeg = Ffmpeg::new().unwrap();
+        let input = sine_stream();
+        let encoding = Encoding::Codec(Codec::Flac, "96K".parse().unwrap());
+
+        let cases = [
+            (timestamp(2.5, Some(6.25)), 3.75),
+            (timestamp(0.0, Some(1.02)), 1.02),
+            (timestamp(7.3, None), 2.7),
+        ];
+        // None of these filters should change the duration of the sine wave
+        let filters: [&[AudioFilter]; 3] = [
+            &[],
+            &[AudioFilter::Gain(-3.0)],
+            &[
+                AudioFilter::TrimSilence {
+                    threshold: -50.0,
+                    min_duration: 0.1,
+                    end_gap: 2.0,
+                },
+                AudioFilter::FadeIn(0.5),
+                AudioFilter::FadeOut {
+                    start: 0.5,
+                    duration: 0.5,
+                },
+            ],
+        ];
+
+        for (timestamp, expected) in cases {
+            for filter in filters {
+                let output = named_tempfile(Extension::Flac).unwrap();
+                ffmpeg
+                    .create_clip(
+                        input.path(),
+                        output.path(),
+                        &timestamp,
+                        filter,
+                        encoding,
+                        &Tags::default(),
+                    )
+                    .unwrap();
+
+                // The reported duration is rounded to the centisecond
+                let actual = duration(output.path());
+                let expected = TimeOffset::from_secs_f64(expected).unwrap();
+                let error = actual
+                    .saturating_sub(expected)
+                    .max(expected.saturating_sub(actual));
+                assert!(
+                    error <= TimeOffset::from_secs_f64(0.005).unwrap(),
+                    "{timestamp:?} with {filter:?}: expected {expected}, got {actual}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(has_ffmpeg), ignore = "needs ffmpeg")]
+    fn cover_art_attachment() {
+        let ffmpeg = Ffmpeg::new().unwrap();
+        let input = sine_stream();
+        let cover = named_tempfile_with_suffix(".png").unwrap();
         assert_success_command(FFMPEG, |cmd| {
             cmd.args(FFXXX_DEFAULT_ARGS)
                 .arg("-y")
-                .args([OsStr::new("-i"), input])
-                .args(["-pass", "2"])
-                .args(["-filter:a", &filter])
-                .args(["-c:a", "libopus", "-b:a", &bitrate.to_string()])
-                .arg(output)
+                .args(["-f", "lavfi", "-i", "color=c=red:s=64x48"])
+                .args(["-frames:v", "1"])
+                .arg(cover.path())
         })
+        .unwrap();
+
+        for ext in [Extension::Mka, Extension::Mkv] {
+            let output = named_tempfile(ext).unwrap();
+            ffmpeg
+                .embed_cover_art(
+                    input.path(),
+                    output.path(),
+                    cover.path(),
+                    true,
+                    &Tags::default(),
+                )
+                .unwrap();
+
+            // e.g. "Stream #0:1: Attachment: mjpeg" followed by its "filename" metadata
+            let res = run_command(
+                FFMPEG,
+                |cmd| cmd.arg("-hide_banner").arg("-i").arg(output.path()),
+                Capture::STDERR,
+            )
+            .unwrap();
+            let stderr = String::from_utf8_lossy(&res.stderr);
+            assert!(stderr.contains("Attachment"), "{ext:?}: {stderr}");
+            assert!(stderr.contains("cover.jpg"), "{ext:?}: {stderr}");
+        }
+
+        let output = named_tempfile(Extension::Webm).unwrap();
+        assert!(ffmpeg
+            .embed_cover_art(
+                input.path(),
+                output.path(),
+                cover.path(),
+                true,
+                &Tags::default()
+            )
+            .is_err());
+    }
+
+    #[test]
+    #[cfg_attr(not(has_ffmpeg), ignore = "needs ffmpeg")]
+    fn measure_clip_loudness() {
+        let ffmpeg = Ffmpeg::new().unwrap();
+        let input = sine_stream();
+
+        let clip = ffmpeg
+            .measure_loudness(input.path(), Some(&timestamp(2.0, Some(5.0))))
+            .unwrap();
+        let stream = ffmpeg.measure_loudness(input.path(), None).unwrap();
+
+        // The sine wave has a constant loudness
+        assert!((clip.integrated - stream.integrated).abs() < 0.5);
+    }
+
+    #[test]
+    fn filters_stream_the_audio() {
+        let trim = AudioFilter::TrimSilence {
+            threshold: -50.0,
+            min_duration: 0.1,
+            end_gap: 2.0,
+        };
+        let fade_out = AudioFilter::FadeOut {
+            start: 117.5,
+            duration: 2.5,
+        };
+
+        // `areverse` would hold the entire clip in memory
+        assert!(!trim.to_ffmpeg().contains("areverse"));
+        assert!(trim.to_ffmpeg().contains("stop_periods=1"));
+        assert_eq!(fade_out.to_ffmpeg(), "afade=t=out:st=117.5:d=2.5");
+    }
+
+    #[test]
+    fn quietest_frame_middle_of_silence() {
+        let frames = [
+            (0.0, "-20.5"),
+            (0.02, "-48.1"),
+            (0.04, "-inf"),
+            (0.06, "-20.1"),
+            (0.08, "-inf"),
+            (0.1, "-inf"),
+            (0.12, "-inf"),
+            (0.14, "-18.3"),
+        ];
+        let stderr: String = frames
+            .iter()
+            .enumerate()
+            .map(|(i, (time, level))| {
+                format!(
+                    "[Parsed_ametadata_1 @ 0x5581] frame:{i}    pts:{}  pts_time:{time}\n\
+                    [Parsed_ametadata_1 @ 0x5581] lavfi.astats.Overall.RMS_level={level}\n",
+                    i * 960
+                )
+            })
+            .collect();
+
+        let quietest = quietest_frame(&stderr).unwrap();
+        assert!((quietest - 0.1).abs() < 1e-9);
+
+        assert_eq!(
+            quietest_frame("size=N/A time=00:00:01.00 bitrate=N/A"),
+            None
+        );
     }
 }
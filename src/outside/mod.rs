@@ -2,5 +2,5 @@ mod command;
 mod ffmpeg;
 mod ytdl;
 
-pub use ffmpeg::{Ffmpeg, StreamTransformer};
+pub use ffmpeg::{AudioFilter, Ffmpeg, StreamTransformer};
 pub use ytdl::{StreamDownloader, Ytdl};
@@ -10,14 +10,9 @@ use miette::{miette, Context, IntoDiagnostic};
 use super::command::{assert_success_command, run_command, Capture, YT_DL, YT_DLP};
 use crate::{
     result::{Error, Result},
-    types::Metadata,
+    types::{Chapter, Comment, Metadata},
 };
 
-/// A list of characters that may cause problems to other programs
-const PROBLEMATIC_CHARS: &[char] = &[
-    '"', '\'', '/', '\\', '|', '~', '$', '#', ':', '*', '<', '>', '?', ',',
-];
-
 /// Interface for downloading streams and their metadata
 pub trait StreamDownloader: Sync + Debug {
     /// Get the playlist's videos IDs.
@@ -32,8 +27,16 @@ pub trait StreamDownloader: Sync + Debug {
     /// Get the video metadata
     fn get_metadata(&self, video_id: &str) -> Result<Metadata>;
 
+    /// Get at most `max_count` top-level comments of the video, the top ones first.
+    fn get_comments(&self, video_id: &str, max_count: u16) -> Result<Vec<Comment>>;
+
     /// Download the audio stream of the video with the corresponding ID.
     fn download_audio(&self, path: &Path, video_id: &str) -> Result<()>;
+
+    /// Download the thumbnail of the video with the corresponding ID.
+    ///
+    /// The image may be in any format, regardless of the path extension.
+    fn download_thumbnail(&self, path: &Path, video_id: &str) -> Result<()>;
 }
 
 /// Interface for the [youtube-dl](https://github.com/ytdl-org/youtube-dl) program
@@ -128,13 +131,9 @@ impl StreamDownloader for Ytdl {
                 .to_owned())
         };
 
-        // Remove potentially problematic characters from the title
         let title = get_key("title")?;
-        let title = title
-            .split(PROBLEMATIC_CHARS)
-            .map(str::trim)
-            .collect::<Vec<_>>()
-            .join(" ");
+
+        let get_opt_key = |key| json.get(key).and_then(|v| v.as_str()).map(str::to_owned);
 
         let duration = json
             .get("duration")
@@ -142,14 +141,69 @@ impl StreamDownloader for Ytdl {
             .as_u64()
             .ok_or_else(|| miette!("Value of key 'duration' is not a u64"))?;
 
+        // Chapters are optional: missing or null when the video does not have any
+        let chapters = match json.get("chapters") {
+            None | Some(serde_json::Value::Null) => vec![],
+            Some(chapters) => serde_json::from_value::<Vec<Chapter>>(chapters.clone())
+                .into_diagnostic()
+                .wrap_err("Could not parse chapters")?,
+        };
+
         Ok(Metadata {
             title,
             duration,
             uploader: get_key("uploader")?,
             description: get_key("description")?,
+            chapters,
+            upload_date: get_opt_key("upload_date"),
+            url: get_opt_key("webpage_url"),
         })
     }
 
+    fn get_comments(&self, video_id: &str, max_count: u16) -> Result<Vec<Comment>> {
+        if self.program != YT_DLP {
+            return Err(miette!("Comments can only be fetched with yt-dlp").into());
+        }
+
+        // Sort by the top comments and skip the replies
+        let extractor_args = format!("youtube:comment_sort=top;max_comments={max_count},all,0");
+        let res = self.run_check_availability(
+            |cmd| {
+                cmd.arg("-q")
+                    .arg("--skip-download")
+                    .arg("--write-comments")
+                    .args(["--extractor-args", &extractor_args])
+                    .arg("-j")
+                    .arg("--")
+                    .arg(video_id)
+            },
+            Capture::STDOUT,
+        )?;
+        let output = String::from_utf8_lossy(&res.stdout);
+
+        let json = serde_json::from_str::<serde_json::Value>(&output)
+            .into_diagnostic()
+            .wrap_err("Could not parse json")?;
+
+        // Comments are missing or null when they are disabled
+        let comments = match json.get("comments") {
+            None | Some(serde_json::Value::Null) => return Ok(vec![]),
+            Some(comments) => comments
+                .as_array()
+                .ok_or_else(|| miette!("Value of key 'comments' is not an array"))?,
+        };
+
+        let comments = comments
+            .iter()
+            .filter(|comment| comment.get("parent").is_none_or(|p| p == "root"))
+            .take(max_count.into())
+            .map(|comment| serde_json::from_value::<Comment>(comment.clone()))
+            .collect::<std::result::Result<_, _>>()
+            .into_diagnostic()
+            .wrap_err("Could not parse comments")?;
+        Ok(comments)
+    }
+
     fn download_audio(&self, path: &Path, video_id: &str) -> Result<()> {
         let res = self.run_check_availability(
             |cmd| {
@@ -173,4 +227,43 @@ impl StreamDownloader for Ytdl {
             Err(miette!("Command did run but was not successful").into())
         }
     }
+
+    fn download_thumbnail(&self, path: &Path, video_id: &str) -> Result<()> {
+        // The thumbnail is written next to the output path with its own extension
+        let stem = path.with_extension("");
+        let res = self.run_check_availability(
+            |cmd| {
+                cmd.arg("-q")
+                    .args([OsStr::new("-o"), stem.as_os_str()])
+                    .arg("--skip-download")
+                    .arg("--write-thumbnail")
+                    .arg("--")
+                    .arg(video_id)
+            },
+            Capture::empty(),
+        )?;
+
+        if !res.status.success() {
+            return Err(miette!("Command did run but was not successful").into());
+        }
+
+        // Find the written thumbnail and move it to the wanted path
+        let dir = stem.parent().unwrap_or(Path::new("."));
+        let stem_name = stem.file_name();
+        let thumbnail = dir
+            .read_dir()
+            .into_diagnostic()?
+            .flatten()
+            .map(|entry| entry.path())
+            .find(|p| p.file_stem() == stem_name && p != path);
+
+        match thumbnail {
+            Some(thumbnail) => Ok(std::fs::rename(thumbnail, path)
+                .into_diagnostic()
+                .wrap_err("Could not move the thumbnail")?),
+            // The thumbnail may have been directly written to the path
+            None if path.metadata().is_ok_and(|m| m.len() > 0) => Ok(()),
+            None => Err(miette!("Thumbnail file not found after download").into()),
+        }
+    }
 }
@@ -0,0 +1,334 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use miette::{Context, IntoDiagnostic, Result};
+use tempfile::NamedTempFile;
+use tracing::{debug, info, warn};
+
+use crate::{
+    database::{CacheDb, StoredStream},
+    filename,
+    io::{hash_file, named_tempfile_in, OUTPUT_TEMPFILE_PREFIX, STALE_TEMPFILE_AGE},
+    types::Extension,
+};
+
+/// A directory keeping the downloaded streams between runs, indexed in the cache.
+///
+/// The streams are evicted when they have not been used for `max_age`,
+/// then the least recently used ones while the store is larger than `max_size`.
+///
+/// They are indexed by their video ID, so that a video removed from the cache
+/// is processed again from its stored stream.
+#[derive(Debug)]
+pub struct StreamStore {
+    dir: PathBuf,
+    /// The maximum total size of the streams, in bytes
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+}
+
+/// The file of a downloaded stream
+#[derive(Debug)]
+pub enum StreamFile {
+    /// A temporary file, deleted once the stream has been processed
+    Temp(NamedTempFile),
+    /// A file kept in the stream store
+    Stored(PathBuf),
+}
+
+impl StreamFile {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Temp(file) => file.path(),
+            Self::Stored(path) => path,
+        }
+    }
+}
+
+impl StreamStore {
+    /// Open the store at the given directory, creating it if needed
+    pub fn open(dir: &Path, max_size: Option<u64>, max_age: Option<Duration>) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not create the stream store directory {dir:?}"))?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_size,
+            max_age,
+        })
+    }
+
+    /// Get the stored stream of the video and mark it as used.
+    ///
+    /// A stream whose file is missing or does not have the recorded size is forgotten.
+    /// The store is not essential, so failures are only reported and the stream is downloaded again.
+    pub fn get(&self, cache: &impl CacheDb, video_id: &str) -> Option<StoredStream> {
+        self.try_get(cache, video_id)
+            .map_err(|report| {
+                warn!("Could not get the stored stream of video {video_id}: {report:?}");
+            })
+            .ok()
+            .flatten()
+    }
+
+    fn try_get(&self, cache: &impl CacheDb, video_id: &str) -> Result<Option<StoredStream>> {
+        let Some(mut stream) = cache.stored_stream(video_id)? else {
+            return Ok(None);
+        };
+
+        let is_valid = std::fs::metadata(&stream.path)
+            .is_ok_and(|meta| meta.is_file() && meta.len() == stream.size);
+        if !is_valid {
+            warn!(
+                "The stored stream {:?} is missing or was modified, downloading it again",
+                stream.path
+            );
+            cache.remove_stored_stream(video_id)?;
+            return Ok(None);
+        }
+
+        stream.last_used = now();
+        cache.set_stored_stream(video_id, &stream)?;
+        Ok(Some(stream))
+    }
+
+    /// Create a temporary file in the store directory to download a stream into,
+    /// so that it can then be stored with a simple rename
+    pub fn tempfile(&self) -> Result<NamedTempFile> {
+        named_tempfile_in(&self.dir, Extension::Mkv)
+    }
+
+    /// Move the downloaded stream into the store and record it in the cache.
+    ///
+    /// Return the stream file and its hash. The store is not essential,
+    /// so failures are only reported and the stream is kept as a temporary file.
+    pub fn store(
+        &self,
+        cache: &impl CacheDb,
+        video_id: &str,
+        file: NamedTempFile,
+    ) -> (StreamFile, Option<String>) {
+        let size = file.as_file().metadata().map(|meta| meta.len());
+        let (size, hash) = match (size, hash_file(file.path())) {
+            (Ok(size), Ok(hash)) => (size, hash),
+            (Err(e), _) => {
+                warn!(
+                    "Could not get the size of the stream of video {video_id}, not storing it: {e}"
+                );
+                return (StreamFile::Temp(file), None);
+            }
+            (_, Err(report)) => {
+                warn!("Could not hash the stream of video {video_id}, not storing it: {report:?}");
+                return (StreamFile::Temp(file), None);
+            }
+        };
+
+        let path = self.dir.join(filename::file_name(
+            video_id,
+            None,
+            Extension::Mkv.with_dot(),
+        ));
+        if let Err(e) = file.persist(&path) {
+            warn!(
+                "Could not move the stream of video {video_id} to {path:?}: {}",
+                e.error
+            );
+            return (StreamFile::Temp(e.file), Some(hash));
+        }
+
+        let stream = StoredStream {
+            path,
+            size,
+            hash,
+            last_used: now(),
+        };
+        match cache.set_stored_stream(video_id, &stream) {
+            Ok(()) => debug!("Stream of video {video_id} stored at {:?}", stream.path),
+            // The file is still used for this run, and replaced on the next download
+            Err(report) => warn!("Could not record the stream of video {video_id}: {report:?}"),
+        }
+        (StreamFile::Stored(stream.path), Some(stream.hash))
+    }
+
+    /// Forget the streams whose file is missing and delete the expired ones,
+    /// then the least recently used ones until the store fits in its maximum size.
+    ///
+    /// The stream files not recorded in the cache and the stale temporary files
+    /// are deleted too.
+    ///
+    /// Must not be called while the stored streams are being processed.
+    pub fn evict(&self, cache: &impl CacheDb) -> Result<()> {
+        let streams = cache.stored_streams()?;
+        let mut total_size: u64 = streams.iter().map(|(_, stream)| stream.size).sum();
+        let now = now();
+
+        let mut nb_evicted = 0;
+        let mut kept = HashSet::new();
+        for (video_id, stream) in streams {
+            let is_missing = !stream.path.is_file();
+            let is_expired = self
+                .max_age
+                .is_some_and(|max_age| now.saturating_sub(stream.last_used) > max_age.as_secs());
+            let is_too_large = self.max_size.is_some_and(|max_size| total_size > max_size);
+            if !(is_missing || is_expired || is_too_large) {
+                kept.insert(stream.path);
+                continue;
+            }
+
+            if !is_missing {
+                if let Err(e) = std::fs::remove_file(&stream.path) {
+                    warn!("Could not delete the stored stream {:?}: {e}", stream.path);
+                    kept.insert(stream.path);
+                    continue;
+                }
+                nb_evicted += 1;
+            }
+            cache.remove_stored_stream(&video_id)?;
+            total_size -= stream.size;
+        }
+
+        nb_evicted += self.remove_unrecorded(&kept)?;
+
+        if nb_evicted != 0 {
+            info!("{nb_evicted} streams evicted from the stream store");
+        }
+        Ok(())
+    }
+
+    /// Delete the stream files of the store that are not in `recorded`,
+    /// e.g. when they could not be recorded in the cache, and the temporary files
+    /// of the interrupted downloads.
+    ///
+    /// Return the number of deleted streams.
+    fn remove_unrecorded(&self, recorded: &HashSet<PathBuf>) -> Result<usize> {
+        let entries = std::fs::read_dir(&self.dir)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Could not list the stream store {:?}", self.dir))?;
+
+        let mut nb_removed = 0;
+        for entry in entries {
+            let path = entry.into_diagnostic()?.path();
+            let Ok(meta) = std::fs::metadata(&path) else {
+                continue;
+            };
+            let is_tempfile = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(OUTPUT_TEMPFILE_PREFIX));
+
+            let to_remove = if is_tempfile {
+                meta.modified()
+                    .ok()
+                    .and_then(|modified| modified.elapsed().ok())
+                    .is_some_and(|age| age > STALE_TEMPFILE_AGE)
+            } else {
+                // Only the stream files, in case other files were put in the store
+                let is_stream = path
+                    .extension()
+                    .is_some_and(|ext| ext == Extension::Mkv.with_no_dot());
+                meta.is_file() && is_stream && !recorded.contains(&path)
+            };
+            if !to_remove {
+                continue;
+            }
+
+            match std::fs::remove_file(&path) {
+                Ok(()) => {
+                    debug!("Deleted the unrecorded file {path:?} of the stream store");
+                    nb_removed += usize::from(!is_tempfile);
+                }
+                Err(e) => warn!("Could not delete the unrecorded file {path:?}: {e}"),
+            }
+        }
+        Ok(nb_removed)
+    }
+}
+
+/// The current time, in seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::database::Sqlite;
+
+    use super::*;
+
+    /// Download a fake stream of `size` bytes into the store
+    fn store_stream(store: &StreamStore, cache: &Sqlite, video_id: &str, size: usize) {
+        let mut file = store.tempfile().unwrap();
+        file.write_all(&vec![0; size]).unwrap();
+        let (file, _) = store.store(cache, video_id, file);
+        assert!(matches!(file, StreamFile::Stored(_)));
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Sqlite::read_or_create(&dir.path().join("cache.sqlite")).unwrap();
+        let store = StreamStore::open(&dir.path().join("streams"), Some(250), None).unwrap();
+
+        for video_id in ["old", "used", "new"] {
+            store_stream(&store, &cache, video_id, 100);
+        }
+
+        // Order the streams by age, then use the oldest but one
+        for (video_id, last_used) in [("old", 10), ("used", 20), ("new", 30)] {
+            let stream = cache.stored_stream(video_id).unwrap().unwrap();
+            let stream = StoredStream {
+                last_used,
+                ..stream
+            };
+            cache.set_stored_stream(video_id, &stream).unwrap();
+        }
+        let used_stream = store.get(&cache, "used").unwrap();
+        assert_eq!(used_stream.size, 100);
+
+        store.evict(&cache).unwrap();
+        assert!(store.get(&cache, "old").is_none());
+        assert!(store.get(&cache, "used").is_some());
+        assert!(store.get(&cache, "new").is_some());
+        assert!(!dir.path().join("streams/old.mkv").exists());
+
+        // A modified stream is downloaded again
+        std::fs::write(&used_stream.path, b"modified").unwrap();
+        assert!(store.get(&cache, "used").is_none());
+    }
+
+    #[test]
+    fn evict_unrecorded_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Sqlite::read_or_create(&dir.path().join("cache.sqlite")).unwrap();
+        let streams = dir.path().join("streams");
+        let store = StreamStore::open(&streams, None, None).unwrap();
+
+        store_stream(&store, &cache, "recorded", 100);
+        std::fs::write(streams.join("unrecorded.mkv"), b"stream").unwrap();
+        std::fs::write(streams.join("notes.txt"), b"notes").unwrap();
+
+        // A download interrupted long ago, and one in progress
+        let stale = store.tempfile().unwrap().into_temp_path().keep().unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(SystemTime::now() - 2 * STALE_TEMPFILE_AGE)
+            .unwrap();
+        let in_progress = store.tempfile().unwrap();
+
+        store.evict(&cache).unwrap();
+        assert!(streams.join("recorded.mkv").exists());
+        assert!(!streams.join("unrecorded.mkv").exists());
+        assert!(streams.join("notes.txt").exists());
+        assert!(!stale.exists());
+        assert!(in_progress.path().exists());
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt::Display;
+
+use clap::{builder::PossibleValue, ValueEnum};
+use serde::Deserialize;
+
+use super::{Bitrate, Extension};
+
+/// An audio codec to encode the output files with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    Opus,
+    Vorbis,
+    Mp3,
+    Aac,
+    Flac,
+}
+
+impl ValueEnum for Codec {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Codec::Opus,
+            Codec::Vorbis,
+            Codec::Mp3,
+            Codec::Aac,
+            Codec::Flac,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(PossibleValue::new(self.name()))
+    }
+}
+
+impl Codec {
+    /// Return the name of the codec, as written in the configuration
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Opus => "opus",
+            Self::Vorbis => "vorbis",
+            Self::Mp3 => "mp3",
+            Self::Aac => "aac",
+            Self::Flac => "flac",
+        }
+    }
+
+    /// Return the name of the `ffmpeg` encoder for this codec
+    pub fn ffmpeg_encoder(self) -> &'static str {
+        match self {
+            Self::Opus => "libopus",
+            Self::Vorbis => "libvorbis",
+            Self::Mp3 => "libmp3lame",
+            Self::Aac => "aac",
+            Self::Flac => "flac",
+        }
+    }
+
+    /// Whether the codec is lossless, and as such does not use a bitrate
+    pub fn is_lossless(self) -> bool {
+        matches!(self, Self::Flac)
+    }
+
+    /// Return the codec used when none is set, depending on the output container
+    pub fn default_for(ext: Extension) -> Self {
+        match ext {
+            Extension::Mp3 => Self::Mp3,
+            Extension::Flac => Self::Flac,
+            Extension::M4a => Self::Aac,
+            _ => Self::Opus,
+        }
+    }
+
+    /// Return the containers this codec can be stored in
+    pub fn supported_extensions(self) -> &'static [Extension] {
+        use Extension::*;
+
+        match self {
+            Self::Opus => &[Ogg, Opus, Mka, Mkv, Webm],
+            Self::Vorbis => &[Ogg, Mka, Mkv, Webm],
+            Self::Mp3 => &[Mp3, Mka, Mkv],
+            Self::Aac => &[M4a, Mka, Mkv],
+            Self::Flac => &[Flac, Ogg, Mka, Mkv],
+        }
+    }
+}
+
+/// How to write the audio stream of an output file
+#[derive(Debug, Clone, Copy)]
+pub enum Encoding {
+    /// Copy the audio stream of the input file, without re-encoding it
+    Copy,
+    /// Encode the audio with the codec, at the given bitrate if the codec is lossy
+    Codec(Codec, Bitrate),
+}
+
+impl Display for Encoding {
+    /// Format as e.g. `opus 96K`, `flac` or `stream copy`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Copy => write!(f, "stream copy"),
+            Self::Codec(codec, _) if codec.is_lossless() => write!(f, "{}", codec.name()),
+            Self::Codec(codec, bitrate) => write!(f, "{} {bitrate}", codec.name()),
+        }
+    }
+}
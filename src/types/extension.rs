@@ -6,29 +6,32 @@ use serde::Deserialize;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Extension {
+    Flac,
+    M4a,
     Mka,
     Mkv,
+    Mp3,
     Ogg,
+    Opus,
     Webm,
 }
 
 impl ValueEnum for Extension {
     fn value_variants<'a>() -> &'a [Self] {
         &[
+            Extension::Flac,
+            Extension::M4a,
             Extension::Mka,
             Extension::Mkv,
+            Extension::Mp3,
             Extension::Ogg,
+            Extension::Opus,
             Extension::Webm,
         ]
     }
 
     fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
-        Some(match self {
-            Extension::Mka => PossibleValue::new("mka"),
-            Extension::Mkv => PossibleValue::new("mkv"),
-            Extension::Ogg => PossibleValue::new("ogg"),
-            Extension::Webm => PossibleValue::new("webm"),
-        })
+        Some(PossibleValue::new(self.with_no_dot()))
     }
 }
 
@@ -37,9 +40,13 @@ impl Extension {
     /// e.g. ".ext"
     pub fn with_dot(self) -> &'static str {
         match self {
+            Self::Flac => ".flac",
+            Self::M4a => ".m4a",
             Self::Mka => ".mka",
             Self::Mkv => ".mkv",
+            Self::Mp3 => ".mp3",
             Self::Ogg => ".ogg",
+            Self::Opus => ".opus",
             Self::Webm => ".webm",
         }
     }
@@ -47,23 +54,15 @@ impl Extension {
     /// Return the extension without the leading dot.
     /// e.g. "ext"
     pub fn with_no_dot(self) -> &'static str {
-        match self {
-            Self::Mka => "mka",
-            Self::Mkv => "mkv",
-            Self::Ogg => "ogg",
-            Self::Webm => "webm",
-        }
+        &self.with_dot()[1..]
     }
 
     /// Parse the raw extension string, stripped of its prefix dot
     pub fn from_no_dot(ext: &str) -> Option<Self> {
-        match ext {
-            "mka" => Some(Self::Mka),
-            "mkv" => Some(Self::Mkv),
-            "ogg" => Some(Self::Ogg),
-            "webm" => Some(Self::Webm),
-            _ => None,
-        }
+        Self::value_variants()
+            .iter()
+            .copied()
+            .find(|e| e.with_no_dot() == ext)
     }
 
     /// Parse the path file extension.
@@ -0,0 +1,85 @@
+use std::fmt::Display;
+
+use miette::{miette, Result};
+
+/// The targets of the EBU R128 loudness normalization
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessTarget {
+    /// Integrated loudness, in LUFS
+    pub integrated: f64,
+    /// Loudness range, in LU
+    pub range: f64,
+    /// Maximum true peak, in dBTP
+    pub true_peak: f64,
+}
+
+impl Default for LoudnessTarget {
+    /// The default targets of the `ffmpeg` `loudnorm` filter
+    fn default() -> Self {
+        Self {
+            integrated: -24.0,
+            range: 7.0,
+            true_peak: -2.0,
+        }
+    }
+}
+
+impl LoudnessTarget {
+    /// Create the loudness targets, verifying that they are in the
+    /// ranges accepted by the `ffmpeg` `loudnorm` filter
+    pub fn new(integrated: f64, range: f64, true_peak: f64) -> Result<Self> {
+        let check = |name: &str, value: f64, min: f64, max: f64| {
+            if (min..=max).contains(&value) {
+                Ok(())
+            } else {
+                Err(miette!(
+                    "{name} must be between {min} and {max}, got {value}"
+                ))
+            }
+        };
+
+        check("Integrated loudness", integrated, -70.0, -5.0)?;
+        check("Loudness range", range, 1.0, 50.0)?;
+        check("True peak", true_peak, -9.0, 0.0)?;
+
+        Ok(Self {
+            integrated,
+            range,
+            true_peak,
+        })
+    }
+}
+
+impl LoudnessTarget {
+    /// Return the gain, in dB, to apply to a stream of the `measured` loudness
+    /// to reach the integrated loudness target without exceeding the true peak one
+    pub fn linear_gain(&self, measured: &LoudnessMeasure) -> f64 {
+        let gain = self.integrated - measured.integrated;
+        let max_gain = self.true_peak - measured.true_peak;
+        gain.min(max_gain)
+    }
+}
+
+impl Display for LoudnessTarget {
+    /// Format as the `loudnorm` filter options, e.g. `I=-24:LRA=7:TP=-2`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "I={}:LRA={}:TP={}",
+            self.integrated, self.range, self.true_peak
+        )
+    }
+}
+
+/// The EBU R128 loudness measured on an audio stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessMeasure {
+    /// Integrated loudness, in LUFS
+    pub integrated: f64,
+    /// Loudness range, in LU
+    pub range: f64,
+    /// True peak, in dBTP
+    pub true_peak: f64,
+    /// Relative gating threshold, in LUFS
+    pub threshold: f64,
+}
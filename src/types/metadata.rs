@@ -1,7 +1,45 @@
+use serde::Deserialize;
+
 #[derive(Debug)]
 pub struct Metadata {
     pub title: String,
     pub uploader: String,
     pub description: String,
     pub duration: u64,
+    pub chapters: Vec<Chapter>,
+    /// The upload date, in the `YYYYMMDD` format
+    pub upload_date: Option<String>,
+    /// The URL of the video page
+    pub url: Option<String>,
+}
+
+/// A video chapter, as defined by the video platform
+#[derive(Debug, Clone, Deserialize)]
+pub struct Chapter {
+    /// The chapter start, in seconds
+    pub start_time: f64,
+    /// The chapter end, in seconds
+    pub end_time: f64,
+    pub title: String,
+}
+
+/// A top-level comment of a video
+#[derive(Debug, Clone, Deserialize)]
+pub struct Comment {
+    pub id: String,
+    pub author: Option<String>,
+    pub text: String,
+    pub like_count: Option<u64>,
+    /// Whether the comment has been pinned by the video uploader
+    pub is_pinned: Option<bool>,
+    /// Whether the comment has been written by the video uploader
+    pub author_is_uploader: Option<bool>,
+}
+
+impl Comment {
+    /// Whether the comment has been both written and pinned by the video uploader,
+    /// so that its timestamps can be trusted as much as the description ones
+    pub fn is_pinned_by_uploader(&self) -> bool {
+        self.is_pinned == Some(true) && self.author_is_uploader == Some(true)
+    }
 }
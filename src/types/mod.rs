@@ -1,9 +1,21 @@
 mod bitrate;
+mod codec;
 mod extension;
+mod loudness;
 mod metadata;
+mod path_template;
+mod tags;
+mod time_offset;
 mod timestamp;
+mod title_cleanup;
 
 pub use bitrate::Bitrate;
+pub use codec::{Codec, Encoding};
 pub use extension::Extension;
-pub use metadata::Metadata;
+pub use loudness::{LoudnessMeasure, LoudnessTarget};
+pub use metadata::{Chapter, Comment, Metadata};
+pub use path_template::{PathTemplate, TemplateValues};
+pub use tags::{Tag, Tags, TAG_LIST};
+pub use time_offset::TimeOffset;
 pub use timestamp::{Timestamp, Timestamps};
+pub use title_cleanup::{TitleCleanup, TITLE_CASE_LIST};
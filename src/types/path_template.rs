@@ -0,0 +1,160 @@
+use std::{path::PathBuf, str::FromStr};
+
+use miette::{miette, Result};
+
+use super::Extension;
+use crate::filename;
+
+/// A variable that can be used in a [`PathTemplate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    Uploader,
+    VideoTitle,
+    VideoId,
+    Track,
+    ClipTitle,
+    Artist,
+    Ext,
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "uploader" => Self::Uploader,
+            "video_title" => Self::VideoTitle,
+            "video_id" => Self::VideoId,
+            "track" => Self::Track,
+            "clip_title" => Self::ClipTitle,
+            "artist" => Self::Artist,
+            "ext" => Self::Ext,
+            _ => return None,
+        })
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Self::Track)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    /// A variable, with the minimum width to zero-pad it to
+    Variable(Variable, usize),
+}
+
+/// The values of the variables used to render a [`PathTemplate`]
+#[derive(Debug)]
+pub struct TemplateValues<'a> {
+    pub uploader: &'a str,
+    pub video_title: &'a str,
+    pub video_id: &'a str,
+    /// The 1-based index of the clip in the video,
+    /// or 0 for the entire video kept along with its clips
+    pub track: usize,
+    pub clip_title: &'a str,
+    /// The clip artist, or the video uploader if unknown
+    pub artist: &'a str,
+    pub ext: Extension,
+}
+
+/// A template of the output file paths, relative to the output directory.
+///
+/// Variables are written between braces, e.g. `{uploader}/{video_title}/{clip_title}.{ext}`.
+/// Numeric variables can be zero-padded, e.g. `{track:02}`.
+/// `/` separates directories, which are created as needed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathTemplate(Vec<Segment>);
+
+impl FromStr for PathTemplate {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut segments = vec![];
+        let mut rest = s;
+
+        while let Some(open) = rest.find('{') {
+            if open != 0 {
+                segments.push(Segment::Literal(rest[..open].to_owned()));
+            }
+
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| miette!("Unclosed '{{' in template '{s}'"))?
+                + open;
+            let var = &rest[open + 1..close];
+
+            let (name, width) = match var.split_once(':') {
+                Some((name, spec)) => {
+                    let width = spec
+                        .strip_prefix('0')
+                        .and_then(|w| w.parse().ok())
+                        .ok_or_else(|| {
+                            miette!("Invalid format '{spec}' in template, expected e.g. '02'")
+                        })?;
+                    (name, width)
+                }
+                None => (var, 0),
+            };
+
+            let variable = Variable::from_name(name)
+                .ok_or_else(|| miette!("Unknown variable '{name}' in template '{s}'"))?;
+            if width != 0 && !variable.is_numeric() {
+                return Err(miette!("Variable '{name}' cannot be zero-padded"));
+            }
+            segments.push(Segment::Variable(variable, width));
+
+            rest = &rest[close + 1..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_owned()));
+        }
+
+        if segments.is_empty() {
+            return Err(miette!("Template is empty"));
+        }
+
+        Ok(Self(segments))
+    }
+}
+
+impl PathTemplate {
+    /// Render the template into a path relative to the output directory,
+    /// **without** the file extension.
+    ///
+    /// Every path component is sanitized so that the variable values
+    /// cannot create other directories or escape the output directory,
+    /// and transliterated to ASCII if `ascii` is set.
+    pub fn render(&self, values: &TemplateValues, ascii: bool) -> PathBuf {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(s) => rendered.push_str(s),
+                Segment::Variable(var, width) => {
+                    let value = match var {
+                        Variable::Uploader => values.uploader.to_owned(),
+                        Variable::VideoTitle => values.video_title.to_owned(),
+                        Variable::VideoId => values.video_id.to_owned(),
+                        Variable::Track => format!("{:0width$}", values.track),
+                        Variable::ClipTitle => values.clip_title.to_owned(),
+                        Variable::Artist => values.artist.to_owned(),
+                        Variable::Ext => values.ext.with_no_dot().to_owned(),
+                    };
+                    // Values must not be able to create directories
+                    rendered.push_str(&value.replace(['/', '\\'], " "));
+                }
+            }
+        }
+
+        // The extension is handled separately to number colliding files
+        let rendered = rendered
+            .strip_suffix(values.ext.with_dot())
+            .unwrap_or(&rendered);
+
+        rendered
+            .split('/')
+            .map(|component| filename::sanitize(component, ascii))
+            .collect()
+    }
+}
@@ -0,0 +1,119 @@
+use serde::Deserialize;
+
+use super::LoudnessMeasure;
+
+/// The loudness reference of ReplayGain 2.0, in LUFS
+const REPLAYGAIN_REFERENCE: f64 = -18.0;
+
+/// The loudness reference of the Opus `R128_*_GAIN` tags, in LUFS
+const R128_REFERENCE: f64 = -23.0;
+
+/// A metadata tag that can be written to the output files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tag {
+    /// The clip title
+    Title,
+    /// The clip artist, or the video uploader
+    Artist,
+    /// The video title and ID
+    Album,
+    /// The clip index and the number of clips in the video
+    Track,
+    /// The video upload date
+    Date,
+    /// The video URL
+    Comment,
+}
+
+pub const TAG_LIST: &[&str] = &["title", "artist", "album", "track", "date", "comment"];
+
+/// The metadata tags to write to an output file.
+///
+/// Tags set to `None` are not written.
+#[derive(Debug, Clone, Default)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// The 1-based track number and the total number of tracks
+    pub track: Option<(usize, usize)>,
+    /// The date, in the `YYYY-MM-DD` format
+    pub date: Option<String>,
+    pub comment: Option<String>,
+    /// The ReplayGain or R128 gain tags
+    pub gain: Vec<(&'static str, String)>,
+}
+
+impl Tags {
+    /// Return the tags as `(key, value)` pairs, as understood by `ffmpeg`
+    pub fn to_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = vec![];
+        let mut push = |key, value: &Option<String>| {
+            if let Some(value) = value {
+                pairs.push((key, value.clone()));
+            }
+        };
+
+        push("title", &self.title);
+        push("artist", &self.artist);
+        push("album", &self.album);
+        push(
+            "track",
+            &self.track.map(|(n, total)| format!("{n}/{total}")),
+        );
+        push("date", &self.date);
+        push("comment", &self.comment);
+
+        pairs.extend(self.gain.iter().cloned());
+        pairs
+    }
+
+    /// Set the gain tags from the loudness of the track and of its album, if known.
+    ///
+    /// Opus files use the `R128_*_GAIN` tags of [RFC 7845](https://www.rfc-editor.org/rfc/rfc7845#section-5.2.1),
+    /// other files use the ReplayGain 2.0 `REPLAYGAIN_*` tags.
+    /// Measures of silent streams, whose loudness is infinite, are ignored.
+    pub fn set_gain(
+        &mut self,
+        track: &LoudnessMeasure,
+        album: Option<&LoudnessMeasure>,
+        opus: bool,
+    ) {
+        self.gain.clear();
+
+        // (measure, R128 gain key, ReplayGain gain key, ReplayGain peak key)
+        let measures = [
+            (
+                Some(track),
+                "R128_TRACK_GAIN",
+                "REPLAYGAIN_TRACK_GAIN",
+                "REPLAYGAIN_TRACK_PEAK",
+            ),
+            (
+                album,
+                "R128_ALBUM_GAIN",
+                "REPLAYGAIN_ALBUM_GAIN",
+                "REPLAYGAIN_ALBUM_PEAK",
+            ),
+        ];
+
+        for (measure, r128_key, gain_key, peak_key) in measures {
+            let Some(measure) = measure.filter(|m| m.integrated.is_finite()) else {
+                continue;
+            };
+
+            if opus {
+                // Q7.8 fixed point number, in dB
+                let gain = ((R128_REFERENCE - measure.integrated) * 256.0).round();
+                let gain = gain.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+                self.gain.push((r128_key, gain.to_string()));
+            } else {
+                let gain = REPLAYGAIN_REFERENCE - measure.integrated;
+                let peak = 10f64.powf(measure.true_peak / 20.0);
+                self.gain.push((gain_key, format!("{gain:.2} dB")));
+                self.gain.push((peak_key, format!("{peak:.6}")));
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, TryFromFloatSecsError},
+};
+
+/// A position in a stream, relative to its start
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct TimeOffset(Duration);
+
+impl TimeOffset {
+    pub const ZERO: Self = Self(Duration::ZERO);
+
+    pub fn from_secs(secs: u64) -> Self {
+        Self(Duration::from_secs(secs))
+    }
+
+    /// Fail if `secs` is negative, not finite or too large, e.g. in untrusted metadata
+    pub fn from_secs_f64(secs: f64) -> Result<Self, TryFromFloatSecsError> {
+        Duration::try_from_secs_f64(secs).map(Self)
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.0.as_secs_f64()
+    }
+
+    /// Return the offset as a whole number of milliseconds, rounded to the nearest one
+    pub fn as_millis(self) -> u64 {
+        ((self.0.as_nanos() + 500_000) / 1_000_000) as u64
+    }
+
+    /// Return the duration between the two offsets, or zero if `earlier` is after `self`
+    pub fn saturating_sub(self, earlier: Self) -> Self {
+        Self(self.0.saturating_sub(earlier.0))
+    }
+}
+
+impl FromStr for TimeOffset {
+    type Err = Box<dyn std::error::Error + Sync + Send>;
+
+    /// Parse either a `[[HH:]MM:]SS[.fff]` timestamp, a `[1h][2m][3[.fff]s]` duration,
+    /// or a plain number of seconds.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.contains(':') {
+            parse_colons(s)
+        } else if s.ends_with(['h', 'm', 's']) {
+            parse_units(s)
+        } else {
+            parse_secs(s)
+        }
+        .map(Self)
+    }
+}
+
+/// Parse a `[[HH:]MM:]SS[.fff]` timestamp
+fn parse_colons(s: &str) -> Result<Duration, Box<dyn std::error::Error + Sync + Send>> {
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() > 3 {
+        return Err(Box::from("Too many ':' separated parts"));
+    }
+
+    let (secs, others) = parts.split_last().unwrap();
+    let secs = parse_secs(secs)?;
+    if secs >= Duration::from_secs(60) {
+        return Err(Box::from("Seconds must be lower than 60"));
+    }
+
+    let mut total: u64 = 0;
+    for (i, part) in others.iter().enumerate() {
+        let n = parse_int(part)?;
+        // Only the leading part can overflow to the upper unit
+        if i != 0 && n >= 60 {
+            return Err(Box::from("Minutes must be lower than 60"));
+        }
+        total = checked(total.checked_mul(60).and_then(|total| total.checked_add(n)))?;
+    }
+
+    let total = checked(total.checked_mul(60))?;
+    checked(Duration::from_secs(total).checked_add(secs))
+}
+
+/// Parse a `[1h][2m][3[.fff]s]` duration
+fn parse_units(s: &str) -> Result<Duration, Box<dyn std::error::Error + Sync + Send>> {
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    let mut last_unit = 0;
+
+    while !rest.is_empty() {
+        let unit_pos = rest.find(['h', 'm', 's']).ok_or("Number without a unit")?;
+        let (value, unit) = (&rest[..unit_pos], &rest[unit_pos..=unit_pos]);
+
+        // Units must be in decreasing order and not repeated
+        let (unit_order, unit_secs) = match unit {
+            "h" => (1, 3600),
+            "m" => (2, 60),
+            _ => (3, 1),
+        };
+        if unit_order <= last_unit {
+            return Err(Box::from("Units must be in the `h`, `m`, `s` order"));
+        }
+        last_unit = unit_order;
+
+        let value = if unit == "s" {
+            parse_secs(value)?
+        } else {
+            Duration::from_secs(checked(parse_int(value)?.checked_mul(unit_secs))?)
+        };
+        total = checked(total.checked_add(value))?;
+
+        rest = &rest[unit_pos + 1..];
+    }
+
+    Ok(total)
+}
+
+/// Parse a `SS[.fff]` number of seconds
+fn parse_secs(s: &str) -> Result<Duration, Box<dyn std::error::Error + Sync + Send>> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    let int = parse_int(int)?;
+
+    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Box::from(format!("'{s}' is not a valid number of seconds")));
+    }
+    // Keep up to the nanoseconds
+    let nanos = format!("{frac:0<9}")[..9].parse::<u32>()?;
+
+    Ok(Duration::new(int, nanos))
+}
+
+/// Turn the overflow of a checked operation into an error
+fn checked<T>(value: Option<T>) -> Result<T, Box<dyn std::error::Error + Sync + Send>> {
+    value.ok_or_else(|| Box::from("Timestamp is too large"))
+}
+
+/// Parse an unsigned integer, refusing any sign or whitespace
+fn parse_int(s: &str) -> Result<u64, Box<dyn std::error::Error + Sync + Send>> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(Box::from(format!("'{s}' is not a valid number")));
+    }
+    Ok(s.parse()?)
+}
+
+impl Display for TimeOffset {
+    /// Format as `HH:MM:SS[.fff]`, which is understood by `ffmpeg`.
+    /// The offset is rounded to the nearest millisecond.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total_millis = self.as_millis();
+        let (secs, millis) = (total_millis / 1000, total_millis % 1000);
+        write!(
+            f,
+            "{:02}:{:02}:{:02}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )?;
+
+        if millis != 0 {
+            write!(f, ".{millis:03}")?;
+        }
+        Ok(())
+    }
+}
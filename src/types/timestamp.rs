@@ -1,31 +1,77 @@
-use std::{fmt::Display, ops::Deref};
+use std::{
+    fmt::Display,
+    ops::{Deref, Range},
+    sync::OnceLock,
+};
 
-use heck::ToTitleCase;
+use miette::{Diagnostic, NamedSource, SourceSpan};
 use regex::Regex;
+use tracing::{debug, warn};
+
+use super::{Chapter, TimeOffset, TitleCleanup};
 
 #[derive(Debug, Clone)]
 pub struct Timestamp {
-    pub t_start: String,
+    pub t_start: TimeOffset,
+    /// The clip end, if explicitly specified.
+    /// Otherwise, the clip lasts until the start of the next one.
+    pub t_end: Option<TimeOffset>,
     pub title: String,
+    /// The clip artist, if it could be extracted
+    pub artist: Option<String>,
 }
 
 impl Timestamp {
-    pub fn to_seconds(tstamp: &str) -> u64 {
-        let mut sec = 0;
-        for n in tstamp.split(':').map(|s| s.parse::<u64>().unwrap()) {
-            sec = 60 * sec + n;
+    /// Whether the timestamp covers the entire stream, from its start to its end
+    pub fn is_entire_stream(&self) -> bool {
+        self.t_start == TimeOffset::ZERO && self.t_end.is_none()
+    }
+
+    /// Check that the timestamp is valid in a stream of the given duration
+    /// and can follow the previous timestamp.
+    fn check(&self, prev: Option<&Timestamp>, duration: TimeOffset) -> Result<(), RejectReason> {
+        if let Some(prev) = prev {
+            if self.t_start <= prev.t_start {
+                return Err(RejectReason::OutOfOrder(prev.t_start));
+            }
         }
-        sec
+
+        self.check_bounds(duration)
+    }
+
+    /// Check that the timestamp is valid in a stream of the given duration
+    fn check_bounds(&self, duration: TimeOffset) -> Result<(), RejectReason> {
+        if self.t_start >= duration {
+            return Err(RejectReason::StartOverflow(duration));
+        }
+
+        if let Some(t_end) = self.t_end {
+            if t_end <= self.t_start {
+                return Err(RejectReason::EndBeforeStart);
+            }
+            if t_end > duration {
+                return Err(RejectReason::EndOverflow(duration));
+            }
+        }
+
+        Ok(())
     }
 }
 
 impl Display for Timestamp {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:>8} - {}", self.t_start, self.title)
+        write!(f, "{:>8} - ", self.t_start.to_string())?;
+        if let Some(t_end) = &self.t_end {
+            write!(f, "{:>8} - ", t_end.to_string())?;
+        }
+        if let Some(artist) = &self.artist {
+            write!(f, "{artist} - ")?;
+        }
+        write!(f, "{}", self.title)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Timestamps(Vec<Timestamp>);
 
 impl Timestamps {
@@ -33,35 +79,474 @@ impl Timestamps {
         Self(data)
     }
 
-    pub fn extract_timestamps(description: &str, clip_regex: &[Regex]) -> Self {
+    /// Extract the timestamps from the description lines matching one of the regexes.
+    /// The rejected lines are reported as coming from `source_name`.
+    ///
+    /// The artist is taken from the `artist` group of the regex if any, otherwise
+    /// it is split from the title if `parse_artist` is set.
+    /// Both are then cleaned up with `cleanup`.
+    ///
+    /// If `detect_tracklist` is set, only the lines of the tracklist block are used:
+    /// the longest run of matching lines, or the first one after a `Tracklist:` header.
+    ///
+    /// Lines whose timestamps cannot be parsed, or are outside of the stream `duration`
+    /// are rejected and returned alongside the valid timestamps. So are the fewest
+    /// lines to reject to keep the others in order, e.g. a stray `1:30` in the prose.
+    pub fn extract_timestamps(
+        description: &str,
+        source_name: &str,
+        clip_regex: &[Regex],
+        duration: TimeOffset,
+        detect_tracklist: bool,
+        parse_artist: bool,
+        cleanup: &TitleCleanup,
+    ) -> (Self, Vec<RejectedTimestamp>) {
+        let mut rejected = vec![];
+        let reject = |span: SourceSpan, reason| RejectedTimestamp {
+            src: NamedSource::new(source_name, description.to_owned()),
+            span,
+            reason,
+        };
+
         // For every line, try every regex until one matches
-        let captures = description
-            .lines()
-            .map(str::trim)
-            .filter_map(|line| clip_regex.iter().find_map(|re| re.captures(line)));
-
-        // For every line that matched one regex, construct the timestamp
-        let timestamps = captures
-            .map(|cap| {
-                let title = cap.name("title").unwrap().as_str();
-                let t_start = cap.name("time").unwrap().as_str();
-
-                // Remove potentially problematic characters from the title
-                let title = title
-                    .split(['\'', '"', '/', '\\', '|', '~', '$', '#'])
-                    .map(str::trim)
-                    .collect::<Vec<_>>()
-                    .join(" ");
-
-                Timestamp {
-                    t_start: t_start.to_owned(),
-                    title: title.to_title_case(),
-                }
-            })
+        let lines: Vec<_> = description.lines().map(str::trim).collect();
+        let captures: Vec<_> = lines
+            .iter()
+            .map(|line| clip_regex.iter().find_map(|re| re.captures(line)))
             .collect();
 
+        let block = if detect_tracklist {
+            let matching: Vec<_> = captures.iter().map(Option::is_some).collect();
+            let block = tracklist_block(&lines, &matching);
+            let ignored = matching.iter().filter(|&&m| m).count() - block.len();
+            if ignored != 0 {
+                debug!("{ignored} timestamp lines ignored outside of the tracklist");
+            }
+            block
+        } else {
+            0..lines.len()
+        };
+
+        // The timestamps valid on their own, with the span of their start
+        let mut candidates: Vec<(Timestamp, SourceSpan)> = vec![];
+        for (line, cap) in lines[block.clone()].iter().zip(&captures[block]) {
+            let Some(cap) = cap else {
+                continue;
+            };
+
+            // Offset of the line in the description, to report rejections
+            let line_offset = line.as_ptr() as usize - description.as_ptr() as usize;
+            let span = |m: regex::Match| SourceSpan::from((line_offset + m.start(), m.len()));
+
+            let title = cap.name("title").unwrap().as_str();
+            let artist = cap.name("artist").map(|m| m.as_str());
+            let m_start = cap.name("time").unwrap();
+            let m_end = cap.name("end");
+
+            let t_start = match m_start.as_str().parse::<TimeOffset>() {
+                Ok(t) => t,
+                Err(err) => {
+                    rejected.push(reject(
+                        span(m_start),
+                        RejectReason::Invalid(err.to_string()),
+                    ));
+                    continue;
+                }
+            };
+            let t_end = match m_end.map(|m| m.as_str().parse::<TimeOffset>()).transpose() {
+                Ok(t) => t,
+                Err(err) => {
+                    let reason = RejectReason::Invalid(err.to_string());
+                    rejected.push(reject(span(m_end.unwrap()), reason));
+                    continue;
+                }
+            };
+
+            let (title, artist) = parse_title(title, artist, parse_artist, cleanup);
+            let timestamp = Timestamp {
+                t_start,
+                t_end,
+                title,
+                artist,
+            };
+
+            match timestamp.check_bounds(duration) {
+                Ok(()) => candidates.push((timestamp, span(m_start))),
+                Err(reason @ (RejectReason::EndBeforeStart | RejectReason::EndOverflow(_))) => {
+                    rejected.push(reject(span(m_end.unwrap()), reason))
+                }
+                Err(reason) => rejected.push(reject(span(m_start), reason)),
+            }
+        }
+
+        let starts: Vec<_> = candidates.iter().map(|(t, _)| t.t_start).collect();
+        let kept = longest_increasing(&starts);
+        let mut timestamps = vec![];
+        for (i, (timestamp, span)) in candidates.into_iter().enumerate() {
+            if kept[i] {
+                timestamps.push(timestamp);
+                continue;
+            }
+
+            // Not kept as it conflicts with the closest kept timestamp before or after it
+            let prev = (0..i).rev().find(|&j| kept[j]);
+            let next = (i + 1..starts.len()).find(|&j| kept[j]);
+            let other = match prev {
+                Some(prev) if starts[prev] >= starts[i] => starts[prev],
+                _ => starts[next.expect("a timestamp can only be out of order with another")],
+            };
+            rejected.push(reject(span, RejectReason::OutOfOrder(other)));
+        }
+        rejected.sort_by_key(|rejected| rejected.span.offset());
+
+        (Timestamps::new(timestamps), rejected)
+    }
+
+    /// Construct the timestamps from the video chapters.
+    ///
+    /// The artist is split from the chapter title if `parse_artist` is set,
+    /// then both are cleaned up with `cleanup`.
+    /// Chapters that are not valid in a stream of the given `duration` are skipped.
+    pub fn from_chapters(
+        chapters: &[Chapter],
+        duration: TimeOffset,
+        parse_artist: bool,
+        cleanup: &TitleCleanup,
+    ) -> Self {
+        let mut timestamps: Vec<Timestamp> = vec![];
+
+        for chapter in chapters {
+            let bounds = TimeOffset::from_secs_f64(chapter.start_time)
+                .and_then(|t_start| Ok((t_start, TimeOffset::from_secs_f64(chapter.end_time)?)));
+            let (t_start, t_end) = match bounds {
+                Ok(bounds) => bounds,
+                Err(e) => {
+                    let reason = RejectReason::Invalid(e.to_string());
+                    warn!("Chapter '{}' skipped: {reason}", chapter.title);
+                    continue;
+                }
+            };
+            let (title, artist) = parse_title(&chapter.title, None, parse_artist, cleanup);
+            let timestamp = Timestamp {
+                t_start,
+                // The last chapter usually ends at the stream end, which may not be
+                // exactly the (rounded) stream duration
+                t_end: (t_end < duration).then_some(t_end),
+                title,
+                artist,
+            };
+
+            match timestamp.check(timestamps.last(), duration) {
+                Ok(()) => timestamps.push(timestamp),
+                Err(reason) => warn!("Chapter '{}' skipped: {reason}", chapter.title),
+            }
+        }
+
+        Timestamps::new(timestamps)
+    }
+
+    /// Construct the timestamps from the tracks of a CUE sheet.
+    ///
+    /// The tracks start at their `INDEX 01`, in `MM:SS:FF` with 75 frames per second,
+    /// and their artist is their `PERFORMER`.
+    /// Tracks without such index, or that are not valid in a stream of the given
+    /// `duration`, are skipped.
+    pub fn from_cue(cue: &str, duration: TimeOffset, cleanup: &TitleCleanup) -> Self {
+        // The title, artist & start of the tracks
+        let mut tracks: Vec<(Option<String>, Option<String>, Option<TimeOffset>)> = vec![];
+
+        for line in cue.lines().map(str::trim) {
+            let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let rest = rest.trim();
+            match (command, tracks.last_mut()) {
+                ("TRACK", _) => tracks.push((None, None, None)),
+                ("TITLE", Some((title, _, _))) => *title = Some(rest.trim_matches('"').to_owned()),
+                ("PERFORMER", Some((_, artist, _))) => {
+                    *artist = Some(rest.trim_matches('"').to_owned())
+                }
+                ("INDEX", Some((_, _, start))) => {
+                    if let Some(("01", time)) = rest.split_once(' ') {
+                        *start = parse_cue_time(time.trim());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut timestamps: Vec<Timestamp> = vec![];
+        for (i, (title, artist, start)) in tracks.into_iter().enumerate() {
+            let title = title.unwrap_or_else(|| format!("Track {:02}", i + 1));
+            let Some(t_start) = start else {
+                warn!("CUE track '{title}' skipped: no valid INDEX 01");
+                continue;
+            };
+
+            let timestamp = Timestamp {
+                t_start,
+                t_end: None,
+                title: cleanup.clean(&title),
+                artist: artist.as_deref().map(|artist| cleanup.clean(artist)),
+            };
+            match timestamp.check(timestamps.last(), duration) {
+                Ok(()) => timestamps.push(timestamp),
+                Err(reason) => warn!("CUE track '{title}' skipped: {reason}"),
+            }
+        }
+
         Timestamps::new(timestamps)
     }
+
+    /// Format the timestamps as a CUE sheet of the audio `file`, e.g. `album.opus`,
+    /// from the given `performer` and with the given album `title`.
+    ///
+    /// The clip ends cannot be represented, the tracks last until the next one.
+    pub fn to_cue(&self, performer: &str, title: &str, file: &str) -> String {
+        // There is no escaping in CUE sheets
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "'"));
+        let file_type = if file.ends_with(".mp3") {
+            "MP3"
+        } else {
+            "WAVE"
+        };
+
+        let mut cue = format!(
+            "PERFORMER {}\nTITLE {}\nFILE {} {file_type}\n",
+            quote(performer),
+            quote(title),
+            quote(file)
+        );
+        for (i, timestamp) in self.iter().enumerate() {
+            cue.push_str(&format!(
+                "  TRACK {:02} AUDIO\n    TITLE {}\n",
+                i + 1,
+                quote(&timestamp.title)
+            ));
+            if let Some(artist) = &timestamp.artist {
+                cue.push_str(&format!("    PERFORMER {}\n", quote(artist)));
+            }
+            cue.push_str(&format!(
+                "    INDEX 01 {}\n",
+                format_cue_time(timestamp.t_start)
+            ));
+        }
+        cue
+    }
+
+    /// Construct the timestamps of the tracks separated by the silences of the stream.
+    ///
+    /// The silences, given in order, are cut out of the tracks. A `None` silence end
+    /// means that the silence lasts until the end of the stream.
+    /// Silences that would create a track shorter than `min_length` are ignored.
+    ///
+    /// The tracks are named `<title> - Part NN`. If the silences do not split
+    /// the stream in at least two tracks, no timestamp is returned.
+    pub fn from_silences(
+        silences: &[(TimeOffset, Option<TimeOffset>)],
+        duration: TimeOffset,
+        min_length: TimeOffset,
+        title: &str,
+    ) -> Self {
+        let mut bounds: Vec<(TimeOffset, Option<TimeOffset>)> = vec![];
+        let mut start = TimeOffset::ZERO;
+
+        for &(silence_start, silence_end) in silences {
+            let silence_end = silence_end.unwrap_or(duration);
+            if silence_start <= start {
+                // Silence at the start of the stream
+                start = start.max(silence_end);
+            } else if silence_start.saturating_sub(start) >= min_length {
+                bounds.push((start, Some(silence_start)));
+                start = silence_end;
+            }
+        }
+
+        if start < duration {
+            match bounds.last_mut() {
+                // Too short to be a track on its own, merge it with the previous one
+                Some((_, end)) if duration.saturating_sub(start) < min_length => *end = None,
+                _ => bounds.push((start, None)),
+            }
+        }
+
+        if bounds.len() < 2 {
+            return Timestamps::new(vec![]);
+        }
+
+        Timestamps::new(
+            bounds
+                .into_iter()
+                .enumerate()
+                .map(|(i, (t_start, t_end))| Timestamp {
+                    t_start,
+                    t_end,
+                    title: format!("{title} - Part {:02}", i + 1),
+                    artist: None,
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Find the lines of the tracklist block: the longest run of consecutive `matching` lines,
+/// or the first run after a header line such as `Tracklist:`, separated from it
+/// by blank lines only.
+fn tracklist_block(lines: &[&str], matching: &[bool]) -> Range<usize> {
+    static HEADER_RE: OnceLock<Regex> = OnceLock::new();
+    let header_re = HEADER_RE.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?i)^\W*(?:track ?list(?:ing)?|tracks|set ?list|song ?list|songs|time ?stamps|chapters)",
+            r"(?:\s*\([^)]*\))?\W*$"
+        ))
+        .unwrap()
+    });
+
+    let mut runs = vec![];
+    let mut start = None;
+    for (i, &matching) in matching.iter().chain([&false]).enumerate() {
+        match (matching, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                runs.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let after_header = lines
+        .iter()
+        .position(|line| header_re.is_match(line))
+        .and_then(|header| {
+            runs.iter().find(|run| run.start > header).filter(|run| {
+                lines[header + 1..run.start]
+                    .iter()
+                    .all(|line| line.is_empty())
+            })
+        });
+
+    after_header
+        .or_else(|| {
+            // The first of the longest runs
+            runs.iter().rev().max_by_key(|run| run.len())
+        })
+        .cloned()
+        .unwrap_or(0..0)
+}
+
+/// Parse a CUE sheet `MM:SS:FF` time, with 75 frames per second
+fn parse_cue_time(time: &str) -> Option<TimeOffset> {
+    let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (Some(Some(mins)), Some(Some(secs)), Some(Some(frames)), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    if secs >= 60 || frames >= 75 {
+        return None;
+    }
+
+    let secs = mins.checked_mul(60)?.checked_add(secs)?;
+    TimeOffset::from_secs_f64(secs as f64 + frames as f64 / 75.0).ok()
+}
+
+/// Format an offset as a CUE sheet `MM:SS:FF` time, with 75 frames per second
+fn format_cue_time(offset: TimeOffset) -> String {
+    let frames = (offset.as_millis() * 75 + 500) / 1000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / 75 / 60,
+        frames / 75 % 60,
+        frames % 75
+    )
+}
+
+/// Clean the clip title and artist.
+///
+/// Without an explicit `artist`, it is split from the title if `parse_artist` is set.
+fn parse_title(
+    title: &str,
+    artist: Option<&str>,
+    parse_artist: bool,
+    cleanup: &TitleCleanup,
+) -> (String, Option<String>) {
+    let (title, artist) = match artist {
+        Some(artist) => (title, Some(artist)),
+        None if parse_artist => match split_artist(title) {
+            Some((artist, title)) => (title, Some(artist)),
+            None => (title, None),
+        },
+        None => (title, None),
+    };
+
+    (
+        cleanup.clean(title),
+        artist
+            .map(|artist| cleanup.clean(artist))
+            .filter(|artist| !artist.is_empty()),
+    )
+}
+
+/// Find the longest strictly increasing subsequence of the offsets, as a mask.
+///
+/// Among the longest ones, the one with the earliest offsets is chosen, so that
+/// a stray late offset at the start is dropped rather than the ones following it.
+fn longest_increasing(offsets: &[TimeOffset]) -> Vec<bool> {
+    // The length of the longest subsequence starting at each offset
+    let mut lengths = vec![0; offsets.len()];
+    for i in (0..offsets.len()).rev() {
+        lengths[i] = 1
+            + (i + 1..offsets.len())
+                .filter(|&j| offsets[j] > offsets[i])
+                .map(|j| lengths[j])
+                .max()
+                .unwrap_or(0);
+    }
+
+    let mut kept = vec![false; offsets.len()];
+    let mut remaining = lengths.iter().copied().max().unwrap_or(0);
+    let mut prev: Option<usize> = None;
+    while remaining != 0 {
+        // The earliest offset that can still start a subsequence long enough
+        let next = (prev.map_or(0, |prev| prev + 1)..offsets.len())
+            .filter(|&j| lengths[j] == remaining)
+            .filter(|&j| prev.is_none_or(|prev| offsets[j] > offsets[prev]))
+            .min_by_key(|&j| offsets[j])
+            .unwrap();
+        kept[next] = true;
+        prev = Some(next);
+        remaining -= 1;
+    }
+    kept
+}
+
+/// Split an `Artist - Title` or `Title by Artist` string into its artist and title
+fn split_artist(title: &str) -> Option<(&str, &str)> {
+    static BY_RE: OnceLock<Regex> = OnceLock::new();
+
+    fn non_empty<'a>(artist: &'a str, title: &'a str) -> Option<(&'a str, &'a str)> {
+        let (artist, title) = (artist.trim(), title.trim());
+        (!artist.is_empty() && !title.is_empty()).then_some((artist, title))
+    }
+
+    // The first dash, as the title may contain others, e.g. "Artist - Title - Remix"
+    let dash = [" - ", " – ", " — "]
+        .iter()
+        .filter_map(|sep| title.find(sep).map(|pos| (pos, sep.len())))
+        .min();
+    if let Some((pos, len)) = dash {
+        return non_empty(&title[..pos], &title[pos + len..]);
+    }
+
+    // The last "by", as the title may contain others, e.g. "Stand by Me by Ben E. King".
+    // A single word before it is part of the title, e.g. "Stand by Me"
+    let by_re =
+        BY_RE.get_or_init(|| Regex::new(r"(?i)^(?P<title>.+)\sby\s(?P<artist>.+)$").unwrap());
+    let cap = by_re.captures(title)?;
+    let by_title = cap.name("title")?.as_str();
+    if by_title.split_whitespace().count() < 2 {
+        return None;
+    }
+    non_empty(cap.name("artist")?.as_str(), by_title)
 }
 
 impl Deref for Timestamps {
@@ -82,3 +567,594 @@ impl Display for Timestamps {
         Ok(())
     }
 }
+
+/// The reason why a timestamp has been rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The timestamp could not be parsed
+    Invalid(String),
+    /// The timestamp is not in order with another one, which is kept
+    OutOfOrder(TimeOffset),
+    /// The timestamp starts after the stream end
+    StartOverflow(TimeOffset),
+    /// The timestamp ends before it starts
+    EndBeforeStart,
+    /// The timestamp ends after the stream end
+    EndOverflow(TimeOffset),
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::Invalid(err) => write!(f, "invalid timestamp: {err}"),
+            RejectReason::OutOfOrder(other) => {
+                write!(f, "not in order with the timestamp {other}")
+            }
+            RejectReason::StartOverflow(duration) => {
+                write!(f, "starts after the end of the video ({duration})")
+            }
+            RejectReason::EndBeforeStart => write!(f, "ends before it starts"),
+            RejectReason::EndOverflow(duration) => {
+                write!(f, "ends after the end of the video ({duration})")
+            }
+        }
+    }
+}
+
+/// A description line whose timestamp has been rejected
+#[derive(Debug, Diagnostic)]
+#[diagnostic(
+    code(gawr::timestamp::rejected),
+    help("This line is ignored, the other timestamps are still used")
+)]
+pub struct RejectedTimestamp {
+    #[source_code]
+    src: NamedSource<String>,
+    #[label("{reason}")]
+    span: SourceSpan,
+    pub reason: RejectReason,
+}
+
+impl Display for RejectedTimestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timestamp rejected: {}", self.reason)
+    }
+}
+
+impl std::error::Error for RejectedTimestamp {}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::title_cleanup::TitleCase;
+
+    use super::*;
+
+    /// The cleanup keeping the titles as they are
+    fn keep_titles() -> TitleCleanup {
+        TitleCleanup {
+            case: TitleCase::Keep,
+            ..Default::default()
+        }
+    }
+
+    fn extract(description: &str, duration: u64) -> (Timestamps, Vec<RejectReason>) {
+        let (timestamps, rejected) = Timestamps::extract_timestamps(
+            description,
+            "description",
+            crate::my_regex::get_default_re_list(),
+            TimeOffset::from_secs(duration),
+            false,
+            false,
+            &keep_titles(),
+        );
+        (timestamps, rejected.into_iter().map(|r| r.reason).collect())
+    }
+
+    #[test]
+    fn parse_time_offsets() {
+        let parse = |s: &str| s.parse::<TimeOffset>().ok();
+        let secs = |secs: f64| TimeOffset::from_secs_f64(secs).ok();
+
+        assert_eq!(parse("03:12"), secs(192.));
+        assert_eq!(parse("1:02:03"), secs(3723.));
+        assert_eq!(parse("75:00"), secs(4500.));
+        assert_eq!(parse("00:01.5"), secs(1.5));
+        assert_eq!(parse("1h02m03s"), secs(3723.));
+        assert_eq!(parse("2m30.25s"), secs(150.25));
+        assert_eq!(parse("45s"), secs(45.));
+        assert_eq!(parse("90"), secs(90.));
+
+        assert_eq!(parse("1:2a"), None);
+        assert_eq!(parse("1:60"), None);
+        assert_eq!(parse("1:60:00"), None);
+        assert_eq!(parse("1:2:3:4"), None);
+        assert_eq!(parse("3s2m"), None);
+        assert_eq!(parse("-1:00"), None);
+        assert_eq!(parse("99999999999999999h"), None);
+        assert_eq!(parse("5124095576030431h59m59s"), None);
+        assert_eq!(parse("999999999999999999:00"), None);
+        assert_eq!(parse("999999999999999999:00:00"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn format_time_offsets() {
+        assert_eq!(TimeOffset::from_secs(3723).to_string(), "01:02:03");
+        assert_eq!(
+            TimeOffset::from_secs_f64(1.5).unwrap().to_string(),
+            "00:00:01.500"
+        );
+    }
+
+    #[test]
+    fn extract_valid_timestamps() {
+        let (timestamps, rejected) = extract("00:00 - intro\n01:30 - first\n1:02:03 - last", 4000);
+
+        assert!(rejected.is_empty());
+        let starts: Vec<_> = timestamps.iter().map(|t| t.t_start).collect();
+        assert_eq!(starts, [0, 90, 3723].map(TimeOffset::from_secs).to_vec());
+    }
+
+    #[test]
+    fn extract_unit_timestamps() {
+        let (timestamps, rejected) =
+            extract("0m0s - intro\n1m30s - first\n1h02m03.5s - last", 4000);
+
+        assert!(rejected.is_empty());
+        let starts: Vec<_> = timestamps.iter().map(|t| t.t_start).collect();
+        assert_eq!(
+            starts,
+            [0., 90., 3723.5].map(|secs| TimeOffset::from_secs_f64(secs).unwrap())
+        );
+    }
+
+    #[test]
+    fn reject_out_of_order() {
+        let (timestamps, rejected) = extract("00:00 - a\n05:00 - b\n03:00 - c\n07:00 - d", 600);
+
+        assert_eq!(timestamps.len(), 3);
+        assert_eq!(
+            rejected,
+            [RejectReason::OutOfOrder(TimeOffset::from_secs(180))]
+        );
+    }
+
+    #[test]
+    fn reject_stray_timestamp() {
+        let description = "Filmed in one take, this clip lasts 1:30\n\
+            00:00 - a\n\
+            03:00 - b\n\
+            06:00 - c";
+        let (timestamps, rejected) = extract(description, 600);
+
+        let starts: Vec<_> = timestamps.iter().map(|t| t.t_start).collect();
+        assert_eq!(starts, [0, 180, 360].map(TimeOffset::from_secs).to_vec());
+        assert_eq!(rejected, [RejectReason::OutOfOrder(TimeOffset::ZERO)]);
+    }
+
+    #[test]
+    fn reject_duplicate() {
+        let (timestamps, rejected) = extract("00:00 - a\n00:00 - b", 600);
+
+        assert_eq!(timestamps.len(), 1);
+        assert_eq!(rejected, [RejectReason::OutOfOrder(TimeOffset::ZERO)]);
+    }
+
+    #[test]
+    fn reject_overflow() {
+        let duration = TimeOffset::from_secs(600);
+        let (timestamps, rejected) = extract("00:00 - a\n09:00 - b\n10:00 - c\n12:00 - d", 600);
+
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(
+            rejected,
+            [
+                RejectReason::StartOverflow(duration),
+                RejectReason::StartOverflow(duration)
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_end_overflow() {
+        let duration = TimeOffset::from_secs(600);
+        let (timestamps, rejected) = extract("00:00 - 05:00 a\n06:00 - 11:00 b", 600);
+
+        assert_eq!(timestamps.len(), 1);
+        assert_eq!(rejected, [RejectReason::EndOverflow(duration)]);
+    }
+
+    #[test]
+    fn reject_end_before_start() {
+        let (timestamps, rejected) = extract("00:00 - 05:00 a\n06:00 - 05:30 b", 600);
+
+        assert_eq!(timestamps.len(), 1);
+        assert_eq!(rejected, [RejectReason::EndBeforeStart]);
+    }
+
+    #[test]
+    fn reject_invalid_with_custom_regex() {
+        let re = Regex::new(r"^(?P<time>\S+) (?P<title>.+)$").unwrap();
+        let (timestamps, rejected) = Timestamps::extract_timestamps(
+            "0:00 a\n1:2a b\n2:00 c",
+            "description",
+            &[re],
+            TimeOffset::from_secs(600),
+            false,
+            false,
+            &TitleCleanup::default(),
+        );
+
+        assert_eq!(timestamps.len(), 2);
+        assert!(matches!(
+            rejected.as_slice(),
+            [RejectedTimestamp {
+                reason: RejectReason::Invalid(_),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn split_artists() {
+        let description = "00:00 - Daft Punk - One More Time - Radio Edit\n\
+            03:50 - Stand by Me by Ben E. King\n\
+            06:45 - Just a Title\n\
+            07:30 - Stand by Me\n\
+            09:00 - Someone – Something";
+        let (timestamps, rejected) = Timestamps::extract_timestamps(
+            description,
+            "description",
+            crate::my_regex::get_default_re_list(),
+            TimeOffset::from_secs(600),
+            false,
+            true,
+            &TitleCleanup::default(),
+        );
+
+        assert!(rejected.is_empty());
+        let parsed: Vec<_> = timestamps
+            .iter()
+            .map(|t| (t.artist.as_deref(), t.title.as_str()))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                (Some("Daft Punk"), "One More Time Radio Edit"),
+                (Some("Ben E King"), "Stand By Me"),
+                (None, "Just A Title"),
+                (None, "Stand By Me"),
+                (Some("Someone"), "Something"),
+            ]
+        );
+    }
+
+    #[test]
+    fn artist_regex_group() {
+        let re = Regex::new(r"^(?P<time>\S+) (?P<title>.+) / (?P<artist>.+)$").unwrap();
+        let (timestamps, _) = Timestamps::extract_timestamps(
+            "0:00 Title / Artist - Band",
+            "description",
+            &[re],
+            TimeOffset::from_secs(600),
+            false,
+            true,
+            &TitleCleanup::default(),
+        );
+
+        // The regex artist is used without splitting the title
+        assert_eq!(timestamps[0].artist.as_deref(), Some("Artist Band"));
+        assert_eq!(timestamps[0].title, "Title");
+    }
+
+    #[test]
+    fn chapters_last_end_is_stream_end() {
+        let chapter = |start_time, end_time| Chapter {
+            start_time,
+            end_time,
+            title: "chapter".to_string(),
+        };
+        let timestamps = Timestamps::from_chapters(
+            &[chapter(0., 100.), chapter(100., 600.4)],
+            TimeOffset::from_secs(600),
+            false,
+            &TitleCleanup::default(),
+        );
+
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(timestamps[0].t_end, Some(TimeOffset::from_secs(100)));
+        assert_eq!(timestamps[1].t_end, None);
+    }
+
+    #[test]
+    fn skip_invalid_chapter_times() {
+        let chapter = |start_time, end_time| Chapter {
+            start_time,
+            end_time,
+            title: "chapter".to_string(),
+        };
+        let timestamps = Timestamps::from_chapters(
+            &[
+                chapter(-5., 100.),
+                chapter(0., f64::NAN),
+                chapter(f64::INFINITY, 200.),
+                chapter(100., 1e300),
+                chapter(100., 200.),
+            ],
+            TimeOffset::from_secs(600),
+            false,
+            &TitleCleanup::default(),
+        );
+
+        assert_eq!(timestamps.len(), 1);
+        assert_eq!(timestamps[0].t_start, TimeOffset::from_secs(100));
+    }
+
+    #[test]
+    fn split_on_silences() {
+        let secs = TimeOffset::from_secs;
+        let silences = [
+            (secs(0), Some(secs(2))),
+            (secs(200), Some(secs(203))),
+            // Would create a track shorter than the minimum length
+            (secs(220), Some(secs(222))),
+            (secs(400), Some(secs(402))),
+            (secs(590), None),
+        ];
+        let timestamps = Timestamps::from_silences(&silences, secs(600), secs(30), "Set");
+
+        let bounds: Vec<_> = timestamps.iter().map(|t| (t.t_start, t.t_end)).collect();
+        assert_eq!(
+            bounds,
+            [
+                (secs(2), Some(secs(200))),
+                (secs(203), Some(secs(400))),
+                (secs(402), Some(secs(590))),
+            ]
+        );
+        assert_eq!(timestamps[0].title, "Set - Part 01");
+        assert_eq!(timestamps[2].title, "Set - Part 03");
+    }
+
+    #[test]
+    fn split_on_silences_merges_short_end() {
+        let secs = TimeOffset::from_secs;
+        let silences = [(secs(200), Some(secs(203))), (secs(580), Some(secs(582)))];
+        let timestamps = Timestamps::from_silences(&silences, secs(600), secs(30), "Set");
+
+        let bounds: Vec<_> = timestamps.iter().map(|t| (t.t_start, t.t_end)).collect();
+        assert_eq!(bounds, [(secs(0), Some(secs(200))), (secs(203), None)]);
+
+        // A single track is not a split
+        let timestamps = Timestamps::from_silences(&silences[..1], secs(210), secs(30), "Set");
+        assert!(timestamps.is_empty());
+    }
+
+    #[test]
+    fn cue_tracks() {
+        let cue = indoc::indoc! {r#"
+            PERFORMER "Someone"
+            TITLE "Some Album"
+            FILE "album.wav" WAVE
+              TRACK 01 AUDIO
+                TITLE "First Song"
+                INDEX 01 00:00:00
+              TRACK 02 AUDIO
+                TITLE "Second Song"
+                INDEX 00 03:10:00
+                INDEX 01 03:12:30
+              TRACK 03 AUDIO
+                INDEX 01 75:00:00
+        "#};
+        let timestamps =
+            Timestamps::from_cue(cue, TimeOffset::from_secs(600), &TitleCleanup::default());
+
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(timestamps[0].title, "First Song");
+        assert_eq!(timestamps[1].title, "Second Song");
+        assert_eq!(
+            timestamps[1].t_start,
+            TimeOffset::from_secs_f64(192.4).unwrap()
+        );
+    }
+
+    #[test]
+    fn cue_round_trip() {
+        let timestamp = |secs, title: &str| Timestamp {
+            t_start: TimeOffset::from_secs_f64(secs).unwrap(),
+            t_end: None,
+            title: title.to_owned(),
+            artist: None,
+        };
+        let timestamps = Timestamps::new(vec![
+            timestamp(0., "First"),
+            timestamp(192.4, "Second"),
+            timestamp(4000., "Third"),
+        ]);
+
+        let cue = timestamps.to_cue("Someone", "The \"Album\"", "album.opus");
+        assert!(cue.contains("TITLE \"The 'Album'\"\nFILE \"album.opus\" WAVE\n"));
+        assert!(cue.contains("INDEX 01 66:40:00"));
+
+        let parsed =
+            Timestamps::from_cue(&cue, TimeOffset::from_secs(5000), &TitleCleanup::default());
+        let starts: Vec<_> = parsed.iter().map(|t| t.t_start).collect();
+        let expected: Vec<_> = timestamps.iter().map(|t| t.t_start).collect();
+        assert_eq!(starts, expected);
+    }
+
+    // The tracklist tests reproduce the false positives reported in music video descriptions,
+    // like sponsor codes or social links with times, in the layouts of mixes, album streams,
+    // soundtracks & live sets. Replace them with anonymized real descriptions when available
+
+    /// Extract the tracklist of the description, as `(start, title)` pairs
+    fn extract_tracklist(description: &str) -> Vec<(String, String)> {
+        let (timestamps, _) = Timestamps::extract_timestamps(
+            description,
+            "description",
+            crate::my_regex::get_default_re_list(),
+            TimeOffset::from_secs(3600),
+            true,
+            false,
+            &keep_titles(),
+        );
+        timestamps
+            .iter()
+            .map(|t| (t.t_start.to_string(), t.title.clone()))
+            .collect()
+    }
+
+    fn tracks(tracks: &[(&str, &str)]) -> Vec<(String, String)> {
+        tracks
+            .iter()
+            .map(|&(start, title)| (start.to_owned(), title.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn tracklist_ignores_sponsor_code_and_social_links() {
+        let description = indoc::indoc! {"
+            Chill beats to study & relax to 🌙
+
+            This mix is sponsored by Example VPN. Use code 10:30 for 10% off
+            Giveaway ends at 12:00
+            Follow me on Twitch, live every Friday 20:00
+            https://twitch.tv/example
+
+            0:00 First Track
+            3:15 Second Track
+            7:02 Third Track
+        "};
+
+        assert_eq!(
+            extract_tracklist(description),
+            tracks(&[
+                ("00:00:00", "First Track"),
+                ("00:03:15", "Second Track"),
+                ("00:07:02", "Third Track"),
+            ])
+        );
+
+        // Without detection, the times of the giveaway & the stream schedule are read
+        // as timestamps, and only rejected for being out of order with the tracklist
+        let (timestamps, rejected) = extract(description, 3600);
+        assert_eq!(timestamps.len(), 3);
+        assert_eq!(rejected.len(), 2);
+    }
+
+    #[test]
+    fn tracklist_after_header() {
+        let description = indoc::indoc! {"
+            lofi hip hop mix 📚 beats to relax/study to
+
+            Giveaway ends at 12:00, comment your favourite track!
+            Live every Friday 20:00
+
+            Tracklist:
+
+            00:00 Sleepy Fish - Beyond the Clouds
+            02:14 Kupla - Owls of the Night
+            04:51 j'san - Autumn Leaves
+            07:30 Philanthrope - Sweet Grass
+
+            🎧 Listen on Spotify: https://open.spotify.com/playlist/xyz
+        "};
+
+        assert_eq!(
+            extract_tracklist(description),
+            tracks(&[
+                ("00:00:00", "Sleepy Fish - Beyond the Clouds"),
+                ("00:02:14", "Kupla - Owls of the Night"),
+                ("00:04:51", "j'san - Autumn Leaves"),
+                ("00:07:30", "Philanthrope - Sweet Grass"),
+            ])
+        );
+    }
+
+    #[test]
+    fn tracklist_is_longest_run() {
+        let description = indoc::indoc! {"
+            Full album stream of \"Night Drive\" by Neon Coast.
+            Pre-order the vinyl: https://neoncoast.example/vinyl
+
+            1. Midnight Highway 0:00
+            2. Chrome Hearts 4:12
+            3. Tail Lights 8:47
+            4. Afterglow (feat. Lumi) 13:05
+
+            Best part: 9:20
+        "};
+
+        assert_eq!(
+            extract_tracklist(description),
+            tracks(&[
+                ("00:00:00", "Midnight Highway"),
+                ("00:04:12", "Chrome Hearts"),
+                ("00:08:47", "Tail Lights"),
+                ("00:13:05", "Afterglow (feat. Lumi)"),
+            ])
+        );
+    }
+
+    #[test]
+    fn tracklist_ignores_sponsor_segment() {
+        let description = indoc::indoc! {"
+            Thanks to our sponsor! Use code OST for 10% off, the sponsor segment ends at 1:10
+
+            1:10 - 3:42 Title Screen
+            3:42 - 6:05 Green Hill
+            6:05 - 9:58 Boss Battle
+        "};
+
+        assert_eq!(
+            extract_tracklist(description),
+            tracks(&[
+                ("00:01:10", "Title Screen"),
+                ("00:03:42", "Green Hill"),
+                ("00:06:05", "Boss Battle"),
+            ])
+        );
+
+        // Without detection, the sponsor line makes the first track a duplicate
+        let (timestamps, rejected) = extract(description, 3600);
+        assert_eq!(timestamps.len(), 3);
+        assert_eq!(
+            timestamps[0].title,
+            "Thanks to our sponsor! Use code OST for 10% off, the sponsor segment ends at"
+        );
+        assert_eq!(
+            rejected,
+            [RejectReason::OutOfOrder(TimeOffset::from_secs(70))]
+        );
+    }
+
+    #[test]
+    fn tracklist_header_over_longest_run() {
+        let description = indoc::indoc! {"
+            Recorded live at Club Example
+
+            — SETLIST —
+            00:00 Intro
+            03:20 Artist A - Track One
+            09:45 Artist B - Track Two
+            15:10 Artist C - Track Three
+
+            Upcoming shows (doors open):
+            Berlin 20:00
+            Paris 19:30
+            London 21:00
+            Madrid 20:30
+            Rome 19:00
+        "};
+
+        assert_eq!(
+            extract_tracklist(description),
+            tracks(&[
+                ("00:00:00", "Intro"),
+                ("00:03:20", "Artist A - Track One"),
+                ("00:09:45", "Artist B - Track Two"),
+                ("00:15:10", "Artist C - Track Three"),
+            ])
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::OnceLock;
+
+use heck::ToTitleCase;
+use regex::Regex;
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+
+/// How to change the case of the titles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleCase {
+    /// The behaviour of the previous versions: remove the characters problematic for
+    /// other programs, and title-case every clip title, e.g. `AC/DC` into `Ac Dc`.
+    ///
+    /// The titles are not normalized nor their whitespaces collapsed.
+    #[default]
+    Legacy,
+    /// Keep the titles as they are
+    Keep,
+    /// Capitalize the words that are entirely in lowercase
+    Title,
+    /// Put the titles entirely in lowercase
+    Lower,
+}
+
+pub const TITLE_CASE_LIST: &[&str] = &["legacy", "keep", "title", "lower"];
+
+/// The characters removed from the clip titles with [`TitleCase::Legacy`]
+const LEGACY_CLIP_CHARS: &[char] = &['\'', '"', '/', '\\', '|', '~', '$', '#'];
+
+/// The characters removed from the video titles with [`TitleCase::Legacy`]
+const LEGACY_VIDEO_CHARS: &[char] = &[
+    '"', '\'', '/', '\\', '|', '~', '$', '#', ':', '*', '<', '>', '?', ',',
+];
+
+/// The normalization applied to the video and clip titles
+#[derive(Debug, Clone, Default)]
+pub struct TitleCleanup {
+    pub case: TitleCase,
+    /// Regexes whose matches are replaced by the associated string, in order
+    pub replacements: Vec<(Regex, String)>,
+    /// Remove the bracketed noise like `[Official Video]` or `(Lyrics)`
+    pub remove_noise: bool,
+}
+
+impl TitleCleanup {
+    /// Clean up a clip title or artist.
+    ///
+    /// Normalize it into its NFC form, remove the noise, apply the replacements,
+    /// collapse the whitespaces then change the case.
+    pub fn clean(&self, title: &str) -> String {
+        if self.case == TitleCase::Legacy {
+            let title = self.remove_noise_and_replace(title.to_owned());
+            return strip_chars(&title, LEGACY_CLIP_CHARS).to_title_case();
+        }
+        self.clean_modern(title)
+    }
+
+    /// Clean up a video title, in the same way as [`Self::clean`]
+    /// except that [`TitleCase::Legacy`] does not change its case
+    pub fn clean_video_title(&self, title: &str) -> String {
+        if self.case == TitleCase::Legacy {
+            let title = self.remove_noise_and_replace(title.to_owned());
+            return strip_chars(&title, LEGACY_VIDEO_CHARS);
+        }
+        self.clean_modern(title)
+    }
+
+    fn clean_modern(&self, title: &str) -> String {
+        let title = self.remove_noise_and_replace(title.nfc().collect());
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        match self.case {
+            TitleCase::Legacy | TitleCase::Keep => title,
+            TitleCase::Title => title_case(&title),
+            TitleCase::Lower => title.to_lowercase(),
+        }
+    }
+
+    fn remove_noise_and_replace(&self, mut title: String) -> String {
+        static NOISE_RE: OnceLock<Regex> = OnceLock::new();
+
+        if self.remove_noise {
+            let noise_re = NOISE_RE.get_or_init(|| {
+                Regex::new(concat!(
+                    r"(?i)[(\[【][^()\[\]【】]*\b(?:official|video|audio|lyrics?|visuali[sz]er|hd|hq|4k|m/?v)\b",
+                    r"[^()\[\]【】]*[)\]】]"
+                ))
+                .unwrap()
+            });
+            title = noise_re.replace_all(&title, " ").into_owned();
+        }
+
+        for (re, replacement) in &self.replacements {
+            title = re.replace_all(&title, replacement.as_str()).into_owned();
+        }
+        title
+    }
+}
+
+/// Remove the characters from the title, trimming the parts around them
+fn strip_chars(title: &str, chars: &[char]) -> String {
+    title
+        .split(chars)
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Capitalize the words that are entirely in lowercase,
+/// keeping the others (e.g. "iPhone", "AC/DC") as they are
+fn title_case(title: &str) -> String {
+    title
+        .split(' ')
+        .map(|word| {
+            if word.chars().any(char::is_uppercase) {
+                return word.to_owned();
+            }
+            // Skip the leading punctuation, e.g. in "(live)"
+            match word.char_indices().find(|(_, c)| c.is_alphanumeric()) {
+                Some((i, c)) => {
+                    let rest = &word[i + c.len_utf8()..];
+                    format!("{}{}{rest}", &word[..i], c.to_uppercase())
+                }
+                None => word.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_by_default() {
+        let cleanup = TitleCleanup::default();
+        assert_eq!(
+            cleanup.clean("stand by me - AC/DC's #1"),
+            "Stand By Me Ac Dc S 1"
+        );
+        assert_eq!(
+            cleanup.clean_video_title("AC/DC: Live at River Plate"),
+            "AC DC Live at River Plate"
+        );
+    }
+
+    #[test]
+    fn keep_case() {
+        let cleanup = TitleCleanup {
+            case: TitleCase::Keep,
+            ..Default::default()
+        };
+        assert_eq!(cleanup.clean("  AC/DC -  iPhone  "), "AC/DC - iPhone");
+        assert_eq!(cleanup.clean("残酷な天使のテーゼ"), "残酷な天使のテーゼ");
+        // "e" followed by a combining acute accent
+        assert_eq!(cleanup.clean("Cafe\u{301}"), "Café");
+    }
+
+    #[test]
+    fn change_case() {
+        let mut cleanup = TitleCleanup {
+            case: TitleCase::Title,
+            ..Default::default()
+        };
+        assert_eq!(
+            cleanup.clean("stand by me (live) - AC/DC on iPhone"),
+            "Stand By Me (Live) - AC/DC On iPhone"
+        );
+
+        cleanup.case = TitleCase::Lower;
+        assert_eq!(cleanup.clean("Stand By ME"), "stand by me");
+    }
+
+    #[test]
+    fn remove_noise() {
+        let cleanup = TitleCleanup {
+            case: TitleCase::Keep,
+            remove_noise: true,
+            ..Default::default()
+        };
+        assert_eq!(cleanup.clean("Song [Official Video]"), "Song");
+        assert_eq!(
+            cleanup.clean("Song (Lyrics) (feat. Someone)"),
+            "Song (feat. Someone)"
+        );
+        assert_eq!(cleanup.clean("曲名【MV】"), "曲名");
+        assert_eq!(
+            cleanup.clean("Song (Acoustic Version)"),
+            "Song (Acoustic Version)"
+        );
+    }
+
+    #[test]
+    fn replace_in_order() {
+        let cleanup = TitleCleanup {
+            replacements: vec![
+                (Regex::new(r"\s*\|.*$").unwrap(), String::new()),
+                (
+                    Regex::new(r"(\w+) ft\. (\w+)").unwrap(),
+                    "$1 feat. $2".to_owned(),
+                ),
+            ],
+            case: TitleCase::Keep,
+            ..Default::default()
+        };
+        assert_eq!(
+            cleanup.clean("Song ft. Someone | My Channel #42"),
+            "Song feat. Someone"
+        );
+    }
+}
@@ -16,3 +16,14 @@ impl<T> MutexUtils<T> for Mutex<T> {
         f(self.lock().unwrap())
     }
 }
+
+/// Format a report and all its causes on a single line.
+///
+/// Useful to store the error somewhere that does not support multiline text nicely.
+pub fn report_oneline(report: &miette::Report) -> String {
+    report
+        .chain()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ")
+}